@@ -0,0 +1,265 @@
+//! Benchmarks of the solver on fixed boards known to be hard for it.
+//!
+//! Every board is built from a fixed seed, so the numbers of different runs
+//! can be compared. Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use mineswapper::backend::{self, Backend};
+use mineswapper::neighbor_iter::NeighborIterable;
+use mineswapper::search;
+use mineswapper::solver::{PartialSolution, SolverError};
+use mineswapper::topology;
+use rand::Rng;
+use rand_core::SeedableRng;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+type Xoshiro = rand_xoshiro::Xoshiro256StarStar;
+
+/// How many solutions are enumerated at most. Independent components
+/// multiply the number of solutions, so it can be too big to count.
+const MAX_SOLUTIONS: usize = 100_000;
+
+/// A board in the middle of a game: where the mines are, and which cells
+/// the player has revealed.
+struct Board {
+    name: &'static str,
+    width: u16,
+    height: u16,
+    mines: Vec<bool>,
+    revealed: Vec<bool>,
+}
+
+impl Board {
+    /// A board where the cells for which "is_revealed" is true are revealed,
+    /// and each of the others holds a mine with probability "density".
+    fn new(
+        name: &'static str,
+        width: u16,
+        height: u16,
+        density: f64,
+        is_revealed: impl Fn(u16, u16) -> bool,
+    ) -> Self {
+        let mut rng = Xoshiro::seed_from_u64(0x5eed);
+        let mut mines = Vec::new();
+        let mut revealed = Vec::new();
+        for row in 0..height {
+            for col in 0..width {
+                let is_revealed = is_revealed(row, col);
+                revealed.push(is_revealed);
+                mines.push(!is_revealed && rng.gen_bool(density));
+            }
+        }
+
+        Self {
+            name,
+            width,
+            height,
+            mines,
+            revealed,
+        }
+    }
+
+    fn idx(&self, row: u16, col: u16) -> usize {
+        row as usize * self.width as usize + col as usize
+    }
+
+    fn is_mine(&self, row: u16, col: u16) -> bool {
+        self.mines[self.idx(row, col)]
+    }
+
+    fn is_revealed(&self, row: u16, col: u16) -> bool {
+        self.revealed[self.idx(row, col)]
+    }
+
+    fn keys(&self) -> impl Iterator<Item = (u16, u16)> {
+        let width = self.width;
+        (0..self.height).flat_map(move |row| (0..width).map(move |col| (row, col)))
+    }
+
+    fn clue(&self, row: u16, col: u16) -> u8 {
+        self.neighbors_of(row, col)
+            .filter(|&(row, col)| self.is_mine(row, col))
+            .count() as u8
+    }
+
+    /// The clues around the hidden cells, as given to the search.
+    fn topology(&self) -> search::Topology {
+        let mut ids = vec![None; self.mines.len()];
+        let mut unknown_count = 0u32;
+        let mut clues = Vec::new();
+        for (row, col) in self.keys().filter(|&(row, col)| self.is_revealed(row, col)) {
+            let mut adjacency = Vec::new();
+            for (row, col) in self.neighbors_of(row, col) {
+                if !self.is_revealed(row, col) {
+                    let id = ids[self.idx(row, col)].get_or_insert_with(|| {
+                        unknown_count += 1;
+                        unknown_count - 1
+                    });
+                    adjacency.push(*id);
+                }
+            }
+            if !adjacency.is_empty() {
+                clues.push(search::Clue {
+                    mine_count: self.clue(row, col),
+                    adjacency,
+                });
+            }
+        }
+
+        search::Topology {
+            unknown_count,
+            clues,
+        }
+    }
+
+    /// The solver, told about every revealed cell, but not solved yet.
+    fn partial_solution(&self, backend: Arc<dyn Backend>) -> PartialSolution {
+        let mine_count = self.mines.iter().filter(|is_mine| **is_mine).count() as u32;
+        let mut sol = PartialSolution::new(
+            self.width,
+            self.height,
+            mine_count,
+            Arc::new(topology::Square),
+            backend,
+        );
+        for (row, col) in self.keys().filter(|&(row, col)| self.is_revealed(row, col)) {
+            sol.add_clue((row, col), self.clue(row, col))
+                .expect("The clues must be consistent");
+        }
+        sol
+    }
+
+    /// A hidden empty cell next to a clue, for the player to reveal.
+    fn next_move(&self) -> (u16, u16) {
+        self.keys()
+            .find(|&(row, col)| {
+                !self.is_revealed(row, col)
+                    && !self.is_mine(row, col)
+                    && self
+                        .neighbors_of(row, col)
+                        .any(|(row, col)| self.is_revealed(row, col))
+            })
+            .expect("There must be a safe cell next to a clue")
+    }
+}
+
+impl NeighborIterable for Board {
+    fn width(&self) -> u16 {
+        self.width
+    }
+
+    fn height(&self) -> u16 {
+        self.height
+    }
+}
+
+fn boards() -> Vec<Board> {
+    vec![
+        // The top half is open, leaving a single frontier across the board:
+        Board::new("long_frontier", 30, 16, 0.2, |row, _| row < 8),
+        // Scattered clues among as many mines as empty cells:
+        Board::new("half_density", 10, 10, 0.5, |row, col| {
+            (row * 7 + col * 3) % 3 == 0
+        }),
+        // Every hidden cell is surrounded by clues:
+        Board::new("checkerboard", 12, 12, 0.3, |row, col| (row + col) % 2 == 0),
+        // Lone clues too far apart to share any hidden cell:
+        Board::new("small_components", 30, 16, 0.2, |row, col| {
+            row % 3 == 1 && col % 3 == 1
+        }),
+    ]
+}
+
+fn backends() -> Vec<(&'static str, Arc<dyn Backend>)> {
+    vec![
+        ("diagram", Arc::new(backend::Diagram)),
+        ("enumerator", Arc::new(backend::Enumerator)),
+        ("sat", Arc::new(backend::Sat)),
+    ]
+}
+
+fn bench_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search");
+    group.sample_size(10);
+    let stop = AtomicBool::new(false);
+
+    for board in boards() {
+        let topology = board.topology();
+        group.bench_function(BenchmarkId::new("solutions", board.name), |b| {
+            b.iter(|| {
+                search::Solutions::new(&topology)
+                    .take(MAX_SOLUTIONS)
+                    .count()
+            })
+        });
+        group.bench_function(
+            BenchmarkId::new("find_solution_in_range", board.name),
+            |b| {
+                b.iter(|| {
+                    search::find_solution_in_range(
+                        &topology,
+                        0..=topology.unknown_count as usize,
+                        &stop,
+                    )
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_find_graph_solutions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("find_graph_solutions");
+    group.sample_size(10);
+
+    for board in boards() {
+        for (name, backend) in backends() {
+            let sol = board.partial_solution(backend);
+            group.bench_function(BenchmarkId::new(name, board.name), |b| {
+                b.iter_batched(
+                    || sol.clone(),
+                    |mut sol| sol.find_graph_solutions().unwrap(),
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_find_acomodating_solution(c: &mut Criterion) {
+    let mut group = c.benchmark_group("find_acomodating_solution");
+    group.sample_size(10);
+
+    for board in boards() {
+        let revealed = board.next_move();
+        for (name, backend) in backends() {
+            let mut sol = board.partial_solution(backend);
+            sol.find_graph_solutions().unwrap();
+            group.bench_function(BenchmarkId::new(name, board.name), |b| {
+                b.iter_batched(
+                    || (sol.clone(), Xoshiro::seed_from_u64(0)),
+                    |(mut sol, mut rng)| {
+                        let survived = sol
+                            .find_acomodating_solution(&mut rng, [revealed], |_, _, _| {
+                                Ok::<_, SolverError>(())
+                            })
+                            .unwrap();
+                        assert!(survived, "The cell must be safe in some layout");
+                    },
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_search,
+    bench_find_graph_solutions,
+    bench_find_acomodating_solution
+);
+criterion_main!(benches);
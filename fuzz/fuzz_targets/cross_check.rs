@@ -0,0 +1,12 @@
+//! Random moves on boards small enough to check every one of them against
+//! the brute force.
+
+#![no_main]
+
+mod game;
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|game: game::Game| {
+    game.play(6, 6, true);
+});
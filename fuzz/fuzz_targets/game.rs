@@ -0,0 +1,99 @@
+//! A random game, as built by the fuzzer from its input.
+
+use arbitrary::Arbitrary;
+use mineswapper::backend::{self, Backend};
+use mineswapper::minefield::Minefield;
+use mineswapper::topology::{self, Topology};
+use mineswapper::verify;
+use rand_core::SeedableRng;
+use std::sync::Arc;
+
+#[derive(Arbitrary, Debug)]
+enum BackendKind {
+    Diagram,
+    Enumerator,
+    Sat,
+}
+
+#[derive(Arbitrary, Debug)]
+enum TopologyKind {
+    Square,
+    Hexagonal,
+    Triangular,
+    Toroidal,
+    Orthogonal,
+    Knight,
+    Radius2,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Move {
+    Reveal(u8, u8),
+    Mark(u8, u8),
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct Game {
+    width: u8,
+    height: u8,
+    mine_count: u16,
+    topology: TopologyKind,
+    backend: BackendKind,
+    seed: u64,
+    moves: Vec<Move>,
+}
+
+impl Game {
+    /// Plays the game on a board of at most "max_width" by "max_height",
+    /// panicking on any error or broken invariant. If "verify" is set, every
+    /// move is also checked against the brute force.
+    pub fn play(&self, max_width: u8, max_height: u8, verify: bool) {
+        let width = u16::from(self.width % max_width) + 1;
+        let height = u16::from(self.height % max_height) + 1;
+        let mine_count = u32::from(self.mine_count) % (u32::from(width) * u32::from(height) + 1);
+        let topology: Arc<dyn Topology> = match self.topology {
+            TopologyKind::Square => Arc::new(topology::Square),
+            TopologyKind::Hexagonal => Arc::new(topology::Hexagonal),
+            TopologyKind::Triangular => Arc::new(topology::Triangular),
+            TopologyKind::Toroidal => Arc::new(topology::Toroidal),
+            TopologyKind::Orthogonal => Arc::new(topology::Neighborhood::ORTHOGONAL),
+            TopologyKind::Knight => Arc::new(topology::Neighborhood::KNIGHT),
+            TopologyKind::Radius2 => Arc::new(topology::Neighborhood::RADIUS_2),
+        };
+        let backend: Arc<dyn Backend> = match self.backend {
+            BackendKind::Diagram => Arc::new(backend::Diagram),
+            BackendKind::Enumerator => Arc::new(backend::Enumerator),
+            BackendKind::Sat => Arc::new(backend::Sat),
+        };
+
+        let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(self.seed);
+        let mut minefield =
+            Minefield::create_random(width, height, mine_count, topology, backend, &mut rng)
+                .expect("The mines must fit in the field");
+        minefield.set_verification(verify);
+
+        for m in self.moves.iter() {
+            let result = match *m {
+                Move::Reveal(row, col) => {
+                    minefield.reveal(&mut rng, u16::from(row) % height, u16::from(col) % width)
+                }
+                Move::Mark(row, col) => minefield
+                    .switch_mark(u16::from(row) % height, u16::from(col) % width)
+                    .map(|_| true),
+            };
+            match result {
+                // The solver is left behind after a loss, so stop here:
+                Ok(false) => return,
+                Ok(true) => (),
+                Err(err) => panic!("{} after {:?}\n{}", err, m, verify::dump(&minefield)),
+            }
+
+            if let Err(err) = minefield.check_invariants() {
+                panic!("{} after {:?}\n{}", err, m, verify::dump(&minefield));
+            }
+            if minefield.is_all_revealed() {
+                return;
+            }
+        }
+    }
+}
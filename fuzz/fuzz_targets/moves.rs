@@ -0,0 +1,11 @@
+//! Random moves on boards up to the expert size, with any backend.
+
+#![no_main]
+
+mod game;
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|game: game::Game| {
+    game.play(30, 16, false);
+});
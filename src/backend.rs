@@ -0,0 +1,185 @@
+use super::bdd::Bdd;
+use super::sat;
+use super::search;
+use bitvec::prelude as bv;
+use std::ops::RangeInclusive;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// Decides if a set of clues, plus some assumptions on the unknowns, can be
+/// satisfied, and finds a solution if so.
+pub trait Backend: Send + Sync {
+    /// Finds a solution to the topology where each unknown in "assumptions"
+    /// is a mine or not, as given, and the total number of mines is within
+    /// "mine_range". Returns None if there is no such solution, or if "stop"
+    /// was set before one was found.
+    fn solve(
+        &self,
+        topology: &search::Topology,
+        assumptions: &[(u32, bool)],
+        mine_range: RangeInclusive<usize>,
+        rng: &mut dyn rand::RngCore,
+        stop: &AtomicBool,
+    ) -> Option<bv::BitVec>;
+
+    /// Whether all the alternatives of each graph should be enumerated, so
+    /// that mine layouts can be sampled exactly uniformly.
+    ///
+    /// Backends meant for boards too hard to enumerate return false, and then
+    /// the layouts are taken from solve(), at the cost of uniformity.
+    fn enumerates_alternatives(&self) -> bool;
+
+    /// The name the backend is found by in by_name().
+    fn name(&self) -> &'static str;
+}
+
+/// Finds the backend with the given name, either "diagram", "enumerator" or
+/// "sat".
+pub fn by_name(name: &str) -> Option<Arc<dyn Backend>> {
+    match name {
+        "diagram" => Some(Arc::new(Diagram)),
+        "enumerator" => Some(Arc::new(Enumerator)),
+        "sat" => Some(Arc::new(Sat)),
+        _ => None,
+    }
+}
+
+/// Saves a backend by its name, with serde's "with" attribute.
+pub(crate) mod serde_by_name {
+    use super::Backend;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::sync::Arc;
+
+    pub fn serialize<S: Serializer>(backend: &Arc<dyn Backend>, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(backend.name())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Arc<dyn Backend>, D::Error> {
+        let name = String::deserialize(d)?;
+        super::by_name(&name)
+            .ok_or_else(|| D::Error::custom(format!("unknown solver backend \"{}\"", name)))
+    }
+}
+
+/// Depth-first enumeration of the solutions with search::Solutions.
+pub struct Enumerator;
+
+impl Backend for Enumerator {
+    fn solve(
+        &self,
+        topology: &search::Topology,
+        assumptions: &[(u32, bool)],
+        mine_range: RangeInclusive<usize>,
+        _rng: &mut dyn rand::RngCore,
+        stop: &AtomicBool,
+    ) -> Option<bv::BitVec> {
+        let mut topology = topology.clone();
+        for (unknown, is_mine) in assumptions {
+            topology.clues.push(search::Clue {
+                mine_count: *is_mine as u8,
+                adjacency: vec![*unknown],
+            });
+        }
+
+        search::find_solution_in_range(&topology, mine_range, stop)
+    }
+
+    fn enumerates_alternatives(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        "enumerator"
+    }
+}
+
+/// Builds the decision diagram of all the solutions with bdd::Bdd, and picks
+/// one of them at random.
+pub struct Diagram;
+
+impl Backend for Diagram {
+    fn solve(
+        &self,
+        topology: &search::Topology,
+        assumptions: &[(u32, bool)],
+        mine_range: RangeInclusive<usize>,
+        mut rng: &mut dyn rand::RngCore,
+        stop: &AtomicBool,
+    ) -> Option<bv::BitVec> {
+        let mut alternatives = Bdd::new(topology, stop);
+        for (unknown, is_mine) in assumptions {
+            alternatives.restrict(*unknown, *is_mine);
+        }
+
+        let (mine_count, _) = alternatives
+            .ln_counts_by_mines()
+            .find(|(mine_count, _)| mine_range.contains(&(*mine_count as usize)))?;
+        alternatives.sample(mine_count, &mut rng)
+    }
+
+    fn enumerates_alternatives(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        "diagram"
+    }
+}
+
+/// Conflict-driven clause learning, with sat::Solver.
+pub struct Sat;
+
+impl Backend for Sat {
+    fn solve(
+        &self,
+        topology: &search::Topology,
+        assumptions: &[(u32, bool)],
+        mine_range: RangeInclusive<usize>,
+        rng: &mut dyn rand::RngCore,
+        stop: &AtomicBool,
+    ) -> Option<bv::BitVec> {
+        use rand::Rng;
+
+        let var_count = topology.unknown_count as usize;
+        let mut solver = sat::Solver::new(var_count);
+
+        for clue in topology.clues.iter() {
+            let lits = clue
+                .adjacency
+                .iter()
+                .map(|unknown| sat::Lit::new(*unknown as usize, true))
+                .collect();
+            solver.add_exactly(lits, clue.mine_count as usize);
+        }
+
+        // The total number of mines, as two cardinality constraints:
+        let (min_mines, max_mines) = mine_range.into_inner();
+        if min_mines > max_mines || min_mines > var_count {
+            return None;
+        }
+        let all_mines = (0..var_count).map(|var| sat::Lit::new(var, true));
+        solver.add_at_most(all_mines.clone().collect(), max_mines);
+        solver.add_at_most(
+            all_mines.map(|lit| lit.negate()).collect(),
+            var_count - min_mines,
+        );
+
+        let assumptions: Vec<sat::Lit> = assumptions
+            .iter()
+            .map(|(unknown, is_mine)| sat::Lit::new(*unknown as usize, *is_mine))
+            .collect();
+
+        // Randomize the values tried first, so that the solutions found vary:
+        let model = solver.solve(&assumptions, |_| rng.gen(), stop)?;
+        Some(model.into_iter().collect())
+    }
+
+    fn enumerates_alternatives(&self) -> bool {
+        false
+    }
+
+    fn name(&self) -> &'static str {
+        "sat"
+    }
+}
@@ -0,0 +1,541 @@
+//! Binary decision diagrams holding all the solutions of a topology, without
+//! listing them one by one.
+
+use super::mine_distribution::ln_add_exp;
+use super::search;
+use bitvec::prelude as bv;
+use itertools::izip;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Edge to the false terminal: there is no solution down this way.
+const FALSE: u32 = u32::MAX;
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+struct Node {
+    /// Node of the next level if the variable is empty, and if it is a mine.
+    children: [u32; 2],
+}
+
+/// All the solutions of a topology, as an ordered binary decision diagram.
+///
+/// Every path from the root to the true terminal tests every unknown, in
+/// the same order, and stands for one solution. Partial assignments leaving
+/// the clues in the same state share the same node, so the size of the
+/// diagram depends on how wide the graph is, and not on how many solutions
+/// it has.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedBdd")]
+pub struct Bdd {
+    /// Unknown tested at each level.
+    order: Vec<u32>,
+    /// Nodes of each level, where the root is node 0 of level 0. Children of
+    /// the last level point to the true terminal, as node 0.
+    levels: Vec<Vec<Node>>,
+    /// counts[l][i][m] is the natural logarithm of the number of paths from
+    /// node i of level l to the true terminal with m mines, which would
+    /// overflow even a f64 on large graphs. Empty for nodes with no path at
+    /// all. The last element is for the true terminal.
+    counts: Vec<Vec<Vec<f64>>>,
+}
+
+/// A diagram as read from a file, before checking that every edge leads to
+/// a node of the next level.
+#[derive(Deserialize)]
+struct UncheckedBdd {
+    order: Vec<u32>,
+    levels: Vec<Vec<Node>>,
+    counts: Vec<Vec<Vec<f64>>>,
+}
+
+impl TryFrom<UncheckedBdd> for Bdd {
+    type Error = &'static str;
+
+    fn try_from(bdd: UncheckedBdd) -> Result<Self, Self::Error> {
+        let n = bdd.order.len();
+        let mut is_ordered = vec![false; n];
+        for unknown in bdd.order.iter() {
+            match is_ordered.get_mut(*unknown as usize) {
+                Some(seen) if !*seen => *seen = true,
+                _ => return Err("the unknowns of the diagram are not in order"),
+            }
+        }
+
+        if bdd.levels.len() != n || bdd.counts.len() != n + 1 {
+            return Err("the diagram doesn't have a level for each unknown");
+        }
+        for (level, nodes) in bdd.levels.iter().enumerate() {
+            // The true terminal is the only node after the last level:
+            let next_len = bdd.levels.get(level + 1).map_or(1, |next| next.len());
+            let children = nodes.iter().flat_map(|node| node.children);
+            if children
+                .filter(|child| *child != FALSE)
+                .any(|child| child as usize >= next_len)
+            {
+                return Err("an edge of the diagram leads to no node");
+            }
+            if bdd.counts[level].len() != nodes.len() {
+                return Err("the diagram doesn't count the paths of each node");
+            }
+        }
+        if bdd.counts[n].len() > 1 || (n > 0 && bdd.counts[n].is_empty()) {
+            return Err("the diagram doesn't have a true terminal");
+        }
+
+        Ok(Self {
+            order: bdd.order,
+            levels: bdd.levels,
+            counts: bdd.counts,
+        })
+    }
+}
+
+impl Bdd {
+    /// Builds the diagram of all the solutions of the topology.
+    ///
+    /// Returns an empty diagram if "stop" is set before it is done.
+    pub fn new(topology: &search::Topology, stop: &AtomicBool) -> Self {
+        let order = search::assignment_order(topology);
+        let n = order.len();
+
+        let impossible = topology
+            .clues
+            .iter()
+            .any(|clue| clue.adjacency.len() < clue.mine_count as usize);
+        if impossible {
+            return Self::empty(order);
+        }
+
+        let mut position = vec![0; n];
+        for (level, unknown) in order.iter().enumerate() {
+            position[*unknown as usize] = level;
+        }
+
+        // What each level must check: the clues containing its unknown,
+        // and how many of their unknowns are left to assign after it.
+        let mut checks = vec![Vec::new(); n];
+        // Levels where each clue gets its first and last unknown assigned.
+        let mut spans = Vec::new();
+        for (clue_idx, clue) in topology.clues.iter().enumerate() {
+            let mut levels: Vec<usize> = clue
+                .adjacency
+                .iter()
+                .map(|unknown| position[*unknown as usize])
+                .collect();
+            levels.sort_unstable();
+
+            for (i, level) in levels.iter().enumerate() {
+                checks[*level].push((clue_idx, levels.len() - i - 1));
+            }
+            if let (Some(first), Some(last)) = (levels.first(), levels.last()) {
+                spans.push((clue_idx, *first, *last));
+            }
+        }
+
+        // The state of a node is the number of mines so far of each clue
+        // partially assigned when the level is reached:
+        let active: Vec<Vec<usize>> = (0..=n)
+            .map(|level| {
+                spans
+                    .iter()
+                    .filter(|(_, first, last)| *first < level && level <= *last)
+                    .map(|(clue_idx, _, _)| *clue_idx)
+                    .collect()
+            })
+            .collect();
+
+        let mut levels = Vec::with_capacity(n);
+        let mut states: Vec<Vec<u8>> = vec![Vec::new()];
+        for level in 0..n {
+            if stop.load(Ordering::Relaxed) {
+                return Self::empty(order);
+            }
+
+            let prev_pos: HashMap<usize, usize> = active[level]
+                .iter()
+                .enumerate()
+                .map(|(pos, clue_idx)| (*clue_idx, pos))
+                .collect();
+
+            let mut next_states = HashMap::<Vec<u8>, u32>::new();
+            let mut nodes = Vec::with_capacity(states.len());
+            for state in states.iter() {
+                let mut node = Node {
+                    children: [FALSE; 2],
+                };
+                for is_mine in [false, true] {
+                    let count_of = |clue_idx: usize| {
+                        let count = prev_pos.get(&clue_idx).map_or(0, |pos| state[*pos]);
+                        let contains = checks[level].iter().any(|(idx, _)| *idx == clue_idx);
+                        count + (contains && is_mine) as u8
+                    };
+
+                    // Either more mines than needed, or not enough
+                    // unknowns left to fulfill the clue:
+                    let is_valid = checks[level].iter().all(|(clue_idx, left)| {
+                        let count = count_of(*clue_idx);
+                        let expected = topology.clues[*clue_idx].mine_count;
+                        count <= expected && count as usize + left >= expected as usize
+                    });
+                    if !is_valid {
+                        continue;
+                    }
+
+                    let next: Vec<u8> = active[level + 1].iter().map(|c| count_of(*c)).collect();
+                    let len = next_states.len() as u32;
+                    node.children[is_mine as usize] = *next_states.entry(next).or_insert(len);
+                }
+                nodes.push(node);
+            }
+
+            let mut next: Vec<(Vec<u8>, u32)> = next_states.into_iter().collect();
+            next.sort_unstable_by_key(|(_, idx)| *idx);
+            states = next.into_iter().map(|(state, _)| state).collect();
+            levels.push(nodes);
+        }
+
+        let mut bdd = Self {
+            order,
+            levels,
+            counts: Vec::new(),
+        };
+        bdd.count_paths(n);
+        bdd
+    }
+
+    /// A diagram without any solution.
+    fn empty(order: Vec<u32>) -> Self {
+        let n = order.len();
+        let mut counts = vec![Vec::new(); n + 1];
+        if n > 0 {
+            counts[n] = vec![vec![0.0]];
+        }
+        Self {
+            order,
+            levels: vec![Vec::new(); n],
+            counts,
+        }
+    }
+
+    /// Counts again the paths from the nodes at "level" and above, removing
+    /// the edges to nodes without any path.
+    fn count_paths(&mut self, level: usize) {
+        let n = self.order.len();
+        self.counts.resize(n + 1, Vec::new());
+        self.counts[n] = vec![vec![0.0]];
+
+        for level in (0..level.min(n)).rev() {
+            let (above, below) = self.counts.split_at_mut(level + 1);
+            let below = &below[0];
+
+            above[level] = self.levels[level]
+                .iter_mut()
+                .map(|node| {
+                    let mut counts = Vec::new();
+                    for (is_mine, child) in node.children.iter_mut().enumerate() {
+                        let child_counts = match *child {
+                            FALSE => continue,
+                            child => &below[child as usize],
+                        };
+                        if child_counts.is_empty() {
+                            *child = FALSE;
+                            continue;
+                        }
+
+                        let len = counts.len().max(child_counts.len() + is_mine);
+                        counts.resize(len, f64::NEG_INFINITY);
+                        for (mines, count) in child_counts.iter().enumerate() {
+                            counts[mines + is_mine] = ln_add_exp(counts[mines + is_mine], *count);
+                        }
+                    }
+                    counts
+                })
+                .collect();
+        }
+    }
+
+    fn root_counts(&self) -> &[f64] {
+        self.counts[0].first().map_or(&[], |counts| &counts[..])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root_counts().is_empty()
+    }
+
+    /// Natural logarithm of the number of solutions for each number of mines
+    /// they can have.
+    pub fn ln_counts_by_mines(&self) -> impl Iterator<Item = (u32, f64)> + '_ {
+        (0u32..)
+            .zip(self.root_counts().iter().copied())
+            .filter(|(_, count)| *count > f64::NEG_INFINITY)
+    }
+
+    /// Keeps only the solutions where "unknown" is a mine or not, as given.
+    ///
+    /// Panics if "unknown" is not one of the topology's.
+    pub fn restrict(&mut self, unknown: u32, is_mine: bool) {
+        let level = self
+            .order
+            .iter()
+            .position(|u| *u == unknown)
+            .expect("Every unknown of the topology is in the order");
+        for node in self.levels[level].iter_mut() {
+            node.children[!is_mine as usize] = FALSE;
+        }
+        self.count_paths(level + 1);
+    }
+
+    /// Picks uniformly one of the solutions with the given number of mines,
+    /// or returns None if there is none.
+    pub fn sample(&self, mines: u32, rng: &mut impl rand::Rng) -> Option<bv::BitVec> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut sol = bv::bitvec![0; self.order.len()];
+
+        let mut left = mines as usize;
+        let mut node = 0;
+        for (level, unknown) in self.order.iter().enumerate() {
+            let children = self.levels[level][node].children;
+            let [empty, mine] = [0, 1].map(|is_mine| match children[is_mine] {
+                FALSE => f64::NEG_INFINITY,
+                _ if left < is_mine => f64::NEG_INFINITY,
+                child => self.counts[level + 1][child as usize]
+                    .get(left - is_mine)
+                    .copied()
+                    .unwrap_or(f64::NEG_INFINITY),
+            });
+            if ln_add_exp(empty, mine) == f64::NEG_INFINITY {
+                return None;
+            }
+
+            // The share of the solutions with a mine, e^mine / (e^empty + e^mine):
+            let is_mine = rng.gen::<f64>() * (1.0 + (empty - mine).exp()) < 1.0;
+            sol.set(*unknown as usize, is_mine);
+            node = children[is_mine as usize] as usize;
+            left -= is_mine as usize;
+        }
+
+        Some(sol)
+    }
+
+    /// Adds up the weights of the solutions where each unknown is empty, and
+    /// where it is a mine, if each solution weights "ln_weights[m]", where m
+    /// is its number of mines, all in natural logarithms.
+    pub fn ln_value_weights(&self, ln_weights: &[f64]) -> Vec<[f64; 2]> {
+        let mut ret = vec![[f64::NEG_INFINITY; 2]; self.order.len()];
+        if self.is_empty() {
+            return ret;
+        }
+
+        // down[i][m] is the logarithm of the number of paths from the root to
+        // node i of the current level with m mines:
+        let mut down = vec![vec![0.0]];
+        for (level, unknown) in self.order.iter().enumerate() {
+            let below = &self.counts[level + 1];
+            let mut next_down = vec![Vec::new(); below.len()];
+
+            for (node, paths) in izip!(&self.levels[level], &down) {
+                for (is_mine, child) in node.children.iter().enumerate() {
+                    if *child == FALSE || paths.is_empty() {
+                        continue;
+                    }
+                    let child = *child as usize;
+
+                    let weight = &mut ret[*unknown as usize][is_mine];
+                    for (above, paths_above) in paths.iter().enumerate() {
+                        for (below, paths_below) in below[child].iter().enumerate() {
+                            let mines = above + is_mine + below;
+                            let ln_weight = ln_weights.get(mines).copied();
+                            *weight = ln_add_exp(
+                                *weight,
+                                paths_above + paths_below + ln_weight.unwrap_or(f64::NEG_INFINITY),
+                            );
+                        }
+                    }
+
+                    let next = &mut next_down[child];
+                    next.resize(next.len().max(paths.len() + is_mine), f64::NEG_INFINITY);
+                    for (above, paths_above) in paths.iter().enumerate() {
+                        next[above + is_mine] = ln_add_exp(next[above + is_mine], *paths_above);
+                    }
+                }
+            }
+
+            down = next_down;
+        }
+
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::SeedableRng;
+
+    fn topology(unknown_count: u32, clues: &[(u8, &[u32])]) -> search::Topology {
+        search::Topology {
+            unknown_count,
+            clues: clues
+                .iter()
+                .map(|(mine_count, adjacency)| search::Clue {
+                    mine_count: *mine_count,
+                    adjacency: adjacency.to_vec(),
+                })
+                .collect(),
+        }
+    }
+
+    fn build(topology: &search::Topology) -> Bdd {
+        Bdd::new(topology, &AtomicBool::new(false))
+    }
+
+    /// The number of solutions for each number of mines, out of the logs.
+    fn counts(bdd: &Bdd) -> Vec<(u32, f64)> {
+        bdd.ln_counts_by_mines()
+            .map(|(mines, ln_count)| (mines, ln_count.exp().round()))
+            .collect()
+    }
+
+    /// Clue A needs one mine among 0, 1 and 2, and clue B one among 2 and
+    /// 3. So either 2 is the only mine, or 3 and one of 0 and 1 are.
+    fn overlapping_clues() -> search::Topology {
+        topology(4, &[(1, &[0, 1, 2]), (1, &[2, 3])])
+    }
+
+    #[test]
+    fn counts_a_single_clue() {
+        assert_eq!(counts(&build(&topology(3, &[(1, &[0, 1, 2])]))), [(1, 3.0)]);
+        assert_eq!(
+            counts(&build(&topology(4, &[(2, &[0, 1, 2, 3])]))),
+            [(2, 6.0)]
+        );
+        assert_eq!(counts(&build(&topology(2, &[(0, &[0, 1])]))), [(0, 1.0)]);
+        assert_eq!(counts(&build(&overlapping_clues())), [(1, 1.0), (2, 2.0)]);
+    }
+
+    #[test]
+    fn restricts_to_the_assumptions() {
+        let mut bdd = build(&overlapping_clues());
+        bdd.restrict(2, true);
+        assert_eq!(counts(&bdd), [(1, 1.0)]);
+
+        let mut bdd = build(&overlapping_clues());
+        bdd.restrict(2, false);
+        assert_eq!(counts(&bdd), [(2, 2.0)]);
+        bdd.restrict(0, false);
+        assert_eq!(counts(&bdd), [(2, 1.0)]);
+        bdd.restrict(1, false);
+        assert!(bdd.is_empty());
+        assert_eq!(counts(&bdd), []);
+    }
+
+    #[test]
+    fn has_no_solution_when_empty() {
+        let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(0);
+        let stop = AtomicBool::new(true);
+        let empties = [
+            Bdd::empty(vec![0, 1]),
+            Bdd::empty(Vec::new()),
+            // More mines than unknowns:
+            build(&topology(2, &[(3, &[0, 1])])),
+            // Clues disagreeing:
+            build(&topology(2, &[(1, &[0, 1]), (2, &[0, 1])])),
+            Bdd::new(&overlapping_clues(), &stop),
+        ];
+        for bdd in empties {
+            assert!(bdd.is_empty());
+            assert_eq!(bdd.sample(0, &mut rng), None);
+            assert_eq!(counts(&bdd), []);
+            let weights = bdd.ln_value_weights(&[0.0; 5]);
+            assert!(weights.iter().flatten().all(|w| *w == f64::NEG_INFINITY));
+        }
+    }
+
+    #[test]
+    fn samples_only_solutions() {
+        let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(0);
+        let topology = overlapping_clues();
+        let bdd = build(&topology);
+
+        let mut seen = Vec::new();
+        for _ in 0..100 {
+            for mines in [1, 2] {
+                let sol = bdd.sample(mines, &mut rng).unwrap();
+                assert_eq!(sol.count_ones(), mines as usize);
+                for clue in topology.clues.iter() {
+                    let found = clue.adjacency.iter().filter(|u| sol[**u as usize]).count();
+                    assert_eq!(found, clue.mine_count as usize);
+                }
+                if !seen.contains(&sol) {
+                    seen.push(sol);
+                }
+            }
+        }
+        assert_eq!(seen.len(), 3);
+        assert_eq!(bdd.sample(3, &mut rng), None);
+        assert_eq!(bdd.sample(0, &mut rng), None);
+    }
+
+    #[test]
+    fn weights_the_values() {
+        let bdd = build(&overlapping_clues());
+        // Solutions with one mine weigh 1, with two 10:
+        let weights: Vec<[f64; 2]> = bdd
+            .ln_value_weights(&[f64::NEG_INFINITY, 0.0, 10f64.ln()])
+            .iter()
+            .map(|ln_weights| ln_weights.map(|w| w.exp().round()))
+            .collect();
+        assert_eq!(
+            weights,
+            [[11.0, 10.0], [11.0, 10.0], [20.0, 1.0], [1.0, 20.0]]
+        );
+    }
+
+    #[test]
+    fn loads_only_valid_diagrams() {
+        let save = |bdd: &Bdd| bincode::serialize(bdd).unwrap();
+        let load = |file: &[u8]| bincode::deserialize::<Bdd>(file);
+
+        let bdd = build(&overlapping_clues());
+        assert_eq!(counts(&load(&save(&bdd)).unwrap()), counts(&bdd));
+        for bdd in [Bdd::empty(Vec::new()), Bdd::empty(vec![0, 1])] {
+            assert!(load(&save(&bdd)).unwrap().is_empty());
+        }
+
+        let mut broken = bdd.clone();
+        broken.levels[1][0].children[0] = 99;
+        assert!(load(&save(&broken)).is_err());
+
+        let mut broken = bdd.clone();
+        broken.levels.pop();
+        assert!(load(&save(&broken)).is_err());
+
+        let mut broken = bdd.clone();
+        broken.order[0] = broken.order[1];
+        assert!(load(&save(&broken)).is_err());
+
+        let mut broken = bdd;
+        broken.counts[2].pop();
+        assert!(load(&save(&broken)).is_err());
+    }
+
+    #[test]
+    fn counts_more_solutions_than_a_f64_holds() {
+        let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(0);
+        // 2 * 2^1098 solutions, as the unknowns without clues are free:
+        let bdd = build(&topology(1100, &[(1, &[0, 1])]));
+        let ln_total = bdd
+            .ln_counts_by_mines()
+            .map(|(_, ln_count)| ln_count)
+            .fold(f64::NEG_INFINITY, ln_add_exp);
+        assert!((ln_total - 1099.0 * 2f64.ln()).abs() < 1e-6);
+
+        let sol = bdd.sample(500, &mut rng).unwrap();
+        assert_eq!(sol.count_ones(), 500);
+        assert!(sol[0] != sol[1]);
+    }
+}
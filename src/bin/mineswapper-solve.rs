@@ -0,0 +1,169 @@
+//! Analyzes a board in progress, without any interface, for scripts.
+//!
+//! The board is read in the notation of `mineswapper::notation`, from the
+//! file given, or from the standard input, and the report says whether it is
+//! consistent, how many layouts of the mines it has, which hidden cells are
+//! forced, and how likely each hidden cell is to hold a mine.
+//!
+//! Exits with 0 if the board is consistent, 1 if it isn't, and 2 if it could
+//! not be read.
+
+use mineswapper::backend::{self, Backend};
+use mineswapper::notation::{Cell, Position};
+use mineswapper::solver::{PartialSolution, SolverError};
+use std::fmt::Write;
+use std::io::Read;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+const USAGE: &str = "\
+usage: mineswapper-solve [--backend diagram|enumerator|sat] [FILE]
+
+Reads the board from FILE, or from the standard input if FILE is missing or
+\"-\". Rows and columns are counted from 0.";
+
+/// The number of layouts, written exactly while a f64 holds it exactly.
+fn format_layouts(ln_layouts: f64) -> String {
+    let log10 = ln_layouts / std::f64::consts::LN_10;
+    if log10 < 15.0 {
+        format!("{}", ln_layouts.exp().round())
+    } else {
+        let exponent = log10.floor();
+        format!("about {:.3}e{}", 10f64.powf(log10 - exponent), exponent)
+    }
+}
+
+/// Writes the report on the position, or returns why it is inconsistent.
+fn analyze(position: &Position, backend: Arc<dyn Backend>) -> Result<String, SolverError> {
+    let mut sol = PartialSolution::from_position(position, backend)?;
+    let ln_layouts = sol.ln_layouts();
+    if ln_layouts == f64::NEG_INFINITY {
+        return Err(SolverError::MineCount);
+    }
+
+    let mut out = String::new();
+    writeln!(out, "consistent: yes").unwrap();
+    writeln!(out, "layouts: {}", format_layouts(ln_layouts)).unwrap();
+
+    let forced = sol.find_forced_cells();
+    for (name, is_mine) in [("mines", true), ("empties", false)] {
+        write!(out, "forced {}:", name).unwrap();
+        for ((row, col), _) in forced.iter().filter(|(_, forced)| *forced == is_mine) {
+            write!(out, " {},{}", row, col).unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+
+    // Percentages of the hidden cells, and the clues between them:
+    writeln!(out, "probabilities:").unwrap();
+    let probabilities = sol.mine_probabilities();
+    for ((cells, probs), row) in position.cells.rows().zip(probabilities.rows()).zip(0u16..) {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(probs)
+            .map(|(cell, prob)| match cell {
+                Cell::Revealed(clue) => format!("{:>4}", clue),
+                Cell::Hidden(_) => format!("{:>3}%", (prob * 100.0).round()),
+            })
+            .collect();
+        writeln!(out, "{:>3}: {}", row, line.join(" ")).unwrap();
+    }
+
+    Ok(out)
+}
+
+fn main() -> ExitCode {
+    let mut backend: Arc<dyn Backend> = Arc::new(backend::Diagram);
+    let mut path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--backend" => match args.next().as_deref().and_then(backend::by_name) {
+                Some(chosen) => backend = chosen,
+                None => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::from(2);
+                }
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
+            }
+        }
+    }
+
+    let text = match path.as_deref() {
+        None | Some("-") => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text).map(|_| text)
+        }
+        Some(path) => std::fs::read_to_string(path),
+    };
+    let position: Position = match text {
+        Ok(text) => match text.parse() {
+            Ok(position) => position,
+            Err(err) => {
+                eprintln!("Invalid board, {}", err);
+                return ExitCode::from(2);
+            }
+        },
+        Err(err) => {
+            eprintln!("Could not read the board: {}", err);
+            return ExitCode::from(2);
+        }
+    };
+
+    match analyze(&position, backend) {
+        Ok(report) => {
+            print!("{}", report);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            println!("consistent: no ({})", err);
+            ExitCode::from(1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyzes_the_1_2_1_pattern() {
+        let position: Position = "mines: 2\n...\n121\n".parse().unwrap();
+        for name in ["diagram", "enumerator", "sat"] {
+            let report = analyze(&position, backend::by_name(name).unwrap()).unwrap();
+            assert_eq!(
+                report,
+                "\
+consistent: yes
+layouts: 1
+forced mines: 0,0 0,2
+forced empties: 0,1
+probabilities:
+  0: 100%   0% 100%
+  1:    1    2    1
+"
+            );
+        }
+
+        let position: Position = "mines: 3\n...\n121\n".parse().unwrap();
+        assert!(analyze(&position, Arc::new(backend::Diagram)).is_err());
+    }
+
+    #[test]
+    fn formats_huge_layout_counts() {
+        assert_eq!(format_layouts(0.0), "1");
+        assert_eq!(
+            format_layouts(100.0 * std::f64::consts::LN_10),
+            "about 1.000e100"
+        );
+    }
+}
@@ -0,0 +1,414 @@
+//! Plays the game in a terminal, for when there is no display to open the
+//! window on, like over SSH.
+//!
+//! The arrow keys (or h, j, k and l) move the cursor, space reveals the tile
+//! under it, or the neighbors of a clue with all its mines flagged, and f
+//! cycles the tile between flagged, question-marked and unmarked. n starts a
+//! new game and q quits.
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::style::{Color, Print, PrintStyledContent, Stylize};
+use crossterm::{cursor, execute, queue, terminal};
+use mineswapper::backend::{self, Backend};
+use mineswapper::minefield::{Content, GameError, Tile, UserMarking};
+use mineswapper::{timing, topology, verify, Minefield, NeighborIterable};
+use rand_core::SeedableRng;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+type Xoshiro = rand_xoshiro::Xoshiro256StarStar;
+
+const USAGE: &str = "usage: mineswapper-tui [beginner|intermediate|expert]";
+
+const KEYS: &str = "arrows/hjkl: move  space: reveal  f: mark  n: new game  q: quit";
+
+/// Width, height and number of mines of each difficulty level.
+fn difficulty(name: &str) -> Option<(u16, u16, u32)> {
+    match name {
+        "beginner" => Some((9, 9, 10)),
+        "intermediate" => Some((16, 16, 40)),
+        "expert" => Some((30, 16, 99)),
+        _ => None,
+    }
+}
+
+enum State {
+    /// The clock starts with the first tile revealed.
+    BeforeStarted,
+    Running(Instant),
+    Finished {
+        duration: Duration,
+        won: bool,
+    },
+}
+
+struct Game {
+    minefield: Minefield,
+    rng: Xoshiro,
+    backend: Arc<dyn Backend>,
+    state: State,
+    cursor: (u16, u16),
+    error: Option<String>,
+}
+
+impl Game {
+    fn new(
+        width: u16,
+        height: u16,
+        mine_count: u32,
+        backend: Arc<dyn Backend>,
+    ) -> Result<Self, GameError> {
+        let mut seed = [0u8; 32];
+        getrandom::getrandom(&mut seed).expect("The system must provide random numbers");
+        let mut rng = Xoshiro::from_seed(seed);
+
+        // The tiles of a terminal are square, so are those of the board:
+        let mut minefield = Minefield::create_random(
+            width,
+            height,
+            mine_count,
+            Arc::new(topology::Square),
+            backend.clone(),
+            &mut rng,
+        )?;
+        minefield.set_verification(verify::is_enabled());
+
+        Ok(Self {
+            minefield,
+            rng,
+            backend,
+            state: State::BeforeStarted,
+            cursor: (height / 2, width / 2),
+            error: None,
+        })
+    }
+
+    fn restart(&mut self) {
+        match Self::new(
+            self.minefield.width(),
+            self.minefield.height(),
+            self.minefield.mine_count,
+            self.backend.clone(),
+        ) {
+            Ok(game) => *self = game,
+            Err(err) => self.error = Some(err.to_string()),
+        }
+    }
+
+    /// Handles a key pressed, and returns whether the game goes on.
+    fn handle_key(&mut self, out: &mut impl Write, key: KeyCode) -> io::Result<bool> {
+        let (row, col) = self.cursor;
+        let (last_row, last_col) = (self.minefield.height() - 1, self.minefield.width() - 1);
+        self.error = None;
+
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Up | KeyCode::Char('k') => self.cursor.0 = row.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.cursor.0 = last_row.min(row + 1),
+            KeyCode::Left | KeyCode::Char('h') => self.cursor.1 = col.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') => self.cursor.1 = last_col.min(col + 1),
+            KeyCode::Char(' ') | KeyCode::Enter => self.reveal(out)?,
+            KeyCode::Char('f') => self.mark(),
+            KeyCode::Char('n') => self.restart(),
+            _ => (),
+        }
+        Ok(true)
+    }
+
+    fn reveal(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let start = match self.state {
+            State::BeforeStarted => Instant::now(),
+            State::Running(start) => start,
+            State::Finished { .. } => return Ok(()),
+        };
+
+        // The solver can take a while on hard boards:
+        self.draw(out, true)?;
+
+        // Nothing changes if the move fails:
+        let (row, col) = self.cursor;
+        let mut minefield = self.minefield.clone();
+        let mut rng = self.rng.clone();
+        match minefield.reveal(&mut rng, row, col) {
+            Ok(survived) => {
+                self.minefield = minefield;
+                self.rng = rng;
+
+                let won = survived && self.minefield.is_all_revealed();
+                self.state = if !survived || won {
+                    State::Finished {
+                        duration: start.elapsed(),
+                        won,
+                    }
+                } else {
+                    State::Running(start)
+                };
+            }
+            Err(err) => self.error = Some(err.to_string()),
+        }
+        Ok(())
+    }
+
+    fn mark(&mut self) {
+        if let State::BeforeStarted | State::Running(_) = self.state {
+            let (row, col) = self.cursor;
+            if let Err(err) = self.minefield.switch_mark(row, col) {
+                self.error = Some(err.to_string());
+            }
+        }
+    }
+
+    /// The lines below the board: the flags and the time, how the game
+    /// ended, and what the player can do.
+    fn status(&self, solving: bool) -> Vec<String> {
+        let counters = &self.minefield.grid.counters;
+        let flags = format!(
+            "Flags: {}/{}",
+            counters.flag_count, self.minefield.mine_count
+        );
+        let mut lines = Vec::new();
+
+        match self.state {
+            State::BeforeStarted => lines.push(flags),
+            State::Running(start) => lines.push(format!(
+                "{}  Elapsed time: {} seconds",
+                flags,
+                start.elapsed().as_secs()
+            )),
+            State::Finished { duration, won } => {
+                lines.push(format!(
+                    "{}  Game time: {:0.06} seconds",
+                    flags,
+                    duration.as_secs_f64()
+                ));
+                lines.push(
+                    if won {
+                        "You won! Congratulations!"
+                    } else {
+                        "You lost! Try again..."
+                    }
+                    .to_string(),
+                );
+            }
+        }
+
+        if let Some(loss) = self.minefield.loss_explanation() {
+            lines.push(
+                if loss.clues.is_empty() {
+                    "There was no room anywhere else for the mines left."
+                } else if loss.uses_mine_count {
+                    "With the number of mines left, the highlighted clues \
+                     show the opened cell had to be a mine."
+                } else {
+                    "The highlighted clues show the opened cell had to be a mine."
+                }
+                .to_string(),
+            );
+        }
+        if solving {
+            lines.push("Solving...".to_string());
+        }
+        if let Some(err) = &self.error {
+            lines.push(format!("Error: {}", err));
+        }
+
+        lines.push(String::new());
+        lines.push(KEYS.to_string());
+        lines
+    }
+
+    fn draw(&self, out: &mut impl Write, solving: bool) -> io::Result<()> {
+        queue!(
+            out,
+            cursor::MoveTo(0, 0),
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+
+        // Where the mines were is only shown once the game is over:
+        let exposed = matches!(self.state, State::Finished { .. });
+        let loss = self.minefield.loss_explanation();
+
+        for (row, tiles) in (0u16..).zip(self.minefield.grid.rows()) {
+            queue!(out, cursor::MoveTo(0, row))?;
+            for (col, tile) in (0u16..).zip(tiles.iter()) {
+                let (text, color) = tile_text(tile, exposed);
+                let mut content = text.with(color);
+                if let Some(loss) = loss {
+                    if loss.revealed.contains(&(row, col)) {
+                        content = content.on(Color::Red);
+                    } else if loss.clues.contains(&(row, col)) {
+                        content = content.on(Color::DarkYellow);
+                    }
+                }
+                if (row, col) == self.cursor {
+                    content = content.reverse();
+                }
+                queue!(out, Print(' '), PrintStyledContent(content))?;
+            }
+        }
+
+        let first_line = self.minefield.height() + 1;
+        for (line, text) in (first_line..).zip(self.status(solving)) {
+            queue!(out, cursor::MoveTo(0, line), Print(text))?;
+        }
+        out.flush()
+    }
+}
+
+fn number_color(clue: u8) -> Color {
+    match clue {
+        1 => Color::Blue,
+        2 => Color::DarkGreen,
+        3 => Color::Red,
+        4 => Color::DarkBlue,
+        5 => Color::DarkRed,
+        6 => Color::DarkCyan,
+        7 => Color::Magenta,
+        _ => Color::Grey,
+    }
+}
+
+fn tile_text(tile: &Tile, exposed: bool) -> (String, Color) {
+    if exposed {
+        match tile {
+            Tile::Hidden(Content::Mine, UserMarking::None) => return ("O".into(), Color::Red),
+            Tile::Hidden(Content::Empty, UserMarking::Flag) => return ("X".into(), Color::Red),
+            _ => (),
+        }
+    }
+
+    match tile {
+        Tile::Hidden(_, UserMarking::None) => (".".into(), Color::DarkGrey),
+        Tile::Hidden(_, UserMarking::Flag) => ("F".into(), Color::Red),
+        Tile::Hidden(_, UserMarking::QuestionMark) => ("?".into(), Color::Yellow),
+        Tile::Revealed(0) => (" ".into(), number_color(0)),
+        Tile::Revealed(clue) => (clue.to_string(), number_color(*clue)),
+    }
+}
+
+/// The terminal in raw mode, on the alternate screen, until dropped, written
+/// to through "out".
+struct RawTerminal<'a, W: Write> {
+    out: &'a mut W,
+}
+
+impl<'a, W: Write> RawTerminal<'a, W> {
+    fn enter(out: &'a mut W) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self { out })
+    }
+}
+
+impl<'a, W: Write> Drop for RawTerminal<'a, W> {
+    fn drop(&mut self) {
+        let _ = execute!(self.out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn run(game: &mut Game, out: &mut impl Write) -> io::Result<()> {
+    let terminal = RawTerminal::enter(out)?;
+    let out = &mut *terminal.out;
+    loop {
+        game.draw(out, false)?;
+
+        // Drawn again at least every second, for the clock:
+        if event::poll(Duration::from_secs(1))? {
+            if let Event::Key(KeyEvent {
+                code,
+                kind: KeyEventKind::Press,
+                ..
+            }) = event::read()?
+            {
+                if !game.handle_key(out, code)? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn main() {
+    let (width, height, mine_count) = match std::env::args().nth(1) {
+        None => difficulty("expert").unwrap(),
+        Some(name) => match difficulty(&name) {
+            Some(settings) => settings,
+            None => {
+                eprintln!("{}", USAGE);
+                std::process::exit(2);
+            }
+        },
+    };
+
+    // The solver backend can be chosen by name from the environment:
+    let backend = match std::env::var("MINESWAPPER_BACKEND") {
+        Ok(name) => backend::by_name(&name).unwrap_or_else(|| {
+            eprintln!("Unknown solver backend \"{}\", using the default.", name);
+            Arc::new(backend::Diagram)
+        }),
+        Err(_) => Arc::new(backend::Diagram),
+    };
+
+    // They would be written over the board:
+    timing::set_enabled(false);
+
+    let mut game =
+        Game::new(width, height, mine_count, backend).expect("The difficulty levels must be valid");
+    if let Err(err) = run(&mut game, &mut io::stdout()) {
+        eprintln!("Terminal error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_with_the_keyboard() {
+        let (width, height, mine_count) = difficulty("beginner").unwrap();
+        let mut game = Game::new(width, height, mine_count, Arc::new(backend::Diagram)).unwrap();
+        let mut out = Vec::new();
+        let mut press = |game: &mut Game, key| assert!(game.handle_key(&mut out, key).unwrap());
+
+        // The cursor stays on the board:
+        for _ in 0..10 {
+            press(&mut game, KeyCode::Up);
+            press(&mut game, KeyCode::Char('l'));
+        }
+        assert_eq!(game.cursor, (0, width - 1));
+
+        press(&mut game, KeyCode::Char('f'));
+        assert_eq!(game.minefield.grid.counters.flag_count, 1);
+        press(&mut game, KeyCode::Char('f'));
+        assert_eq!(game.minefield.grid.counters.flag_count, 0);
+        assert!(matches!(
+            game.minefield.grid.get(0, width - 1),
+            Tile::Hidden(_, UserMarking::QuestionMark)
+        ));
+
+        // Nothing is known before the first move, so it can't lose:
+        press(&mut game, KeyCode::Char('j'));
+        press(&mut game, KeyCode::Char(' '));
+        assert!(matches!(
+            game.minefield.grid.get(1, width - 1),
+            Tile::Revealed(_)
+        ));
+        assert!(matches!(
+            game.state,
+            State::Running(_) | State::Finished { won: true, .. }
+        ));
+        assert!(game.error.is_none());
+
+        assert!(!game.handle_key(&mut out, KeyCode::Char('q')).unwrap());
+    }
+
+    #[test]
+    fn shows_any_clue() {
+        assert_eq!(tile_text(&Tile::Revealed(0), false).0, " ");
+        assert_eq!(tile_text(&Tile::Revealed(8), false).0, "8");
+        assert_eq!(tile_text(&Tile::Revealed(24), false).0, "24");
+    }
+}
@@ -0,0 +1,158 @@
+use super::{number_color, FilledStyle, Message};
+use iced::widget::canvas::{self, event, Cursor, Event, Frame, Geometry, Path, Stroke, Text};
+use iced::{mouse, Point, Rectangle, Size};
+use iced_native::Theme;
+use mineswapper::minefield::{Content, Minefield, Tile, UserMarking};
+use mineswapper::NeighborIterable;
+use std::collections::HashMap;
+
+/// How many pixels a unit of the outlines of the tiles is.
+const SCALE: f32 = 30.0;
+
+/// The board drawn on a canvas, each tile in the shape the topology gives
+/// it, for the topologies whose tiles are not squares, so can't be buttons.
+pub struct Board<'a> {
+    pub minefield: &'a Minefield,
+    /// Whether the game is over, to show where the mines were.
+    pub exposed: bool,
+    pub highlighted: HashMap<(u16, u16), FilledStyle>,
+}
+
+impl<'a> Board<'a> {
+    fn keys(&self) -> impl Iterator<Item = (u16, u16)> {
+        let (width, height) = (self.minefield.width(), self.minefield.height());
+        (0..height).flat_map(move |row| (0..width).map(move |col| (row, col)))
+    }
+
+    /// The outline of the tile, in pixels.
+    fn outline(&self, row: u16, col: u16) -> Vec<Point> {
+        self.minefield
+            .topology()
+            .outline(row, col)
+            .into_iter()
+            .map(|(x, y)| Point::new(x * SCALE, y * SCALE))
+            .collect()
+    }
+
+    /// The size of the canvas the whole board fits in.
+    pub fn size(&self) -> Size {
+        self.keys()
+            .flat_map(|(row, col)| self.outline(row, col))
+            .fold(Size::ZERO, |size, corner| {
+                Size::new(size.width.max(corner.x), size.height.max(corner.y))
+            })
+    }
+
+    /// The tile under the point, if any.
+    fn tile_at(&self, point: Point) -> Option<(u16, u16)> {
+        self.keys()
+            .find(|&(row, col)| contains(&self.outline(row, col), point))
+    }
+}
+
+/// Whether the point is inside the polygon, counting how many of its sides a
+/// ray going right from the point crosses.
+fn contains(polygon: &[Point], point: Point) -> bool {
+    let mut inside = false;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) * (b.x - a.x) / (b.y - a.y)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+impl<'a> canvas::Program<Message> for Board<'a> {
+    type State = ();
+
+    fn update(
+        &self,
+        _state: &mut (),
+        event: Event,
+        bounds: Rectangle,
+        cursor: Cursor,
+    ) -> (event::Status, Option<Message>) {
+        let tile = match cursor
+            .position_in(&bounds)
+            .and_then(|point| self.tile_at(point))
+        {
+            Some(tile) => tile,
+            None => return (event::Status::Ignored, None),
+        };
+
+        // Like the buttons of the square boards, on release:
+        match event {
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => (
+                event::Status::Captured,
+                Some(Message::Reveal(tile.0, tile.1)),
+            ),
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Right)) => {
+                (event::Status::Captured, Some(Message::Mark(tile.0, tile.1)))
+            }
+            _ => (event::Status::Ignored, None),
+        }
+    }
+
+    fn draw(&self, _state: &(), theme: &Theme, bounds: Rectangle, _: Cursor) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        let hidden = theme.extended_palette().primary.strong;
+
+        for (row, col) in self.keys() {
+            let outline = self.outline(row, col);
+            let path = Path::new(|builder| {
+                builder.move_to(outline[0]);
+                for corner in &outline[1..] {
+                    builder.line_to(*corner);
+                }
+                builder.close();
+            });
+
+            let tile = *self.minefield.grid.get(row, col);
+            let highlight = self.highlighted.get(&(row, col));
+            let (background, text_color) = match (tile, highlight) {
+                (_, Some(style)) => (style.0, iced::Color::BLACK),
+                (Tile::Revealed(_), None) => (FilledStyle::REVEALED.0, iced::Color::BLACK),
+                (Tile::Hidden(..), None) => (hidden.color, hidden.text),
+            };
+            frame.fill(&path, background);
+            frame.stroke(
+                &path,
+                Stroke::default()
+                    .with_color(theme.palette().background)
+                    .with_width(1.0),
+            );
+
+            let content = match tile {
+                Tile::Hidden(Content::Mine, UserMarking::None) if self.exposed => "O".to_owned(),
+                Tile::Hidden(Content::Empty, UserMarking::Flag) if self.exposed => "X".to_owned(),
+                Tile::Hidden(_, UserMarking::None) | Tile::Revealed(0) => continue,
+                Tile::Hidden(_, UserMarking::Flag) => "F".to_owned(),
+                Tile::Hidden(_, UserMarking::QuestionMark) => "?".to_owned(),
+                Tile::Revealed(clue) => clue.to_string(),
+            };
+            let color = match tile {
+                Tile::Revealed(clue) => number_color(clue),
+                Tile::Hidden(..) => text_color,
+            };
+            // The centroid, the middle of the triangles as of the hexagons:
+            let count = outline.len() as f32;
+            let center = outline.iter().fold(Point::ORIGIN, |sum, corner| {
+                Point::new(sum.x + corner.x / count, sum.y + corner.y / count)
+            });
+            frame.fill_text(Text {
+                content,
+                position: center,
+                color,
+                size: 14.0,
+                horizontal_alignment: iced::alignment::Horizontal::Center,
+                vertical_alignment: iced::alignment::Vertical::Center,
+                ..Text::default()
+            });
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
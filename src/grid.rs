@@ -1,7 +1,25 @@
 pub trait GridCounters<T> {
-    fn notify_change(&mut self, from: &T, to: &T);
+    type Error;
+
+    /// Called before a cell is changed. If an error is returned, the cell
+    /// is left as it was.
+    fn notify_change(&mut self, from: &T, to: &T) -> Result<(), Self::Error>;
+}
+
+/// For grids that don't need to count anything.
+impl<T> GridCounters<T> for () {
+    type Error = std::convert::Infallible;
+
+    fn notify_change(&mut self, _from: &T, _to: &T) -> Result<(), Self::Error>
+    {
+        Ok(())
+    }
 }
 
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "UncheckedGrid<T, I, C>")]
+#[serde(bound(deserialize = "T: serde::Deserialize<'de>, \
+    I: serde::Deserialize<'de> + Copy + Into<usize>, C: serde::Deserialize<'de>"))]
 pub struct Grid<T, I, C> {
     pub counters: C,
     data: Vec<T>,
@@ -9,6 +27,31 @@ pub struct Grid<T, I, C> {
     h: I
 }
 
+/// A grid as read from a file, before checking it has a value for each cell,
+/// which get() and set() rely on.
+#[derive(serde::Deserialize)]
+struct UncheckedGrid<T, I, C> {
+    counters: C,
+    data: Vec<T>,
+    w: I,
+    h: I
+}
+
+impl<T, I: Copy + Into<usize>, C> TryFrom<UncheckedGrid<T, I, C>> for Grid<T, I, C> {
+    type Error = &'static str;
+
+    fn try_from(grid: UncheckedGrid<T, I, C>) -> Result<Self, Self::Error>
+    {
+        if grid.w.into().checked_mul(grid.h.into()) != Some(grid.data.len()) {
+            return Err("the grid doesn't have a value for each cell");
+        }
+
+        Ok(Self {
+            counters: grid.counters, data: grid.data, w: grid.w, h: grid.h
+        })
+    }
+}
+
 pub struct RowsIter<'a, T, I, C> {
     grid: &'a Grid<T, I, C>,
     idx: usize
@@ -68,7 +111,7 @@ impl<T, M, I, C> Grid<T, I, C> where
         self.h
     }
 
-    pub fn rows(&self) -> RowsIter<T, I, C> {
+    pub fn rows(&self) -> RowsIter<'_, T, I, C> {
         RowsIter {
             grid: self,
             idx: 0
@@ -91,7 +134,7 @@ impl<T, M, I, C> Grid<T, I, C> where
         }
     }
 
-    pub fn set(&mut self, row: I, col: I, val: T)
+    pub fn set(&mut self, row: I, col: I, val: T) -> Result<(), C::Error>
     {
         let idx = self.to_idx(row, col);
         // Use unsafe here because values are already checked by to_idx()
@@ -99,8 +142,27 @@ impl<T, M, I, C> Grid<T, I, C> where
             self.data.get_unchecked_mut(idx)
         };
 
-        self.counters.notify_change(ptr, &val);
+        self.counters.notify_change(ptr, &val)?;
 
         *ptr = val;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_only_whole_grids()
+    {
+        let grid = Grid::from_vec(3u16, 2u16, (), vec![1u8, 2, 3, 4, 5, 6]).unwrap();
+        let loaded: Grid<u8, u16, ()> =
+            bincode::deserialize(&bincode::serialize(&grid).unwrap()).unwrap();
+        assert_eq!(loaded.rows().collect::<Vec<_>>(), [[1, 2, 3], [4, 5, 6]]);
+
+        // Claiming more cells than there are values:
+        let truncated = bincode::serialize(&((), vec![1u8, 2, 3], 3u16, 2u16)).unwrap();
+        assert!(bincode::deserialize::<Grid<u8, u16, ()>>(&truncated).is_err());
     }
 }
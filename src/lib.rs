@@ -0,0 +1,50 @@
+//! The game logic of Mineswapper, independent of any interface.
+//!
+//! A game is a [`Minefield`], which moves the mines around as the player
+//! reveals tiles, as long as a [`PartialSolution`] of the revealed clues
+//! allows:
+//!
+//! ```
+//! use mineswapper::{backend, topology, Minefield};
+//! use rand_core::SeedableRng;
+//! use std::sync::Arc;
+//!
+//! let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(0);
+//! let mut minefield = Minefield::create_random(
+//!     9,
+//!     9,
+//!     10,
+//!     Arc::new(topology::Square),
+//!     Arc::new(backend::Diagram),
+//!     &mut rng,
+//! )
+//! .unwrap();
+//! // Nothing is known yet, so no tile can be a certain loss:
+//! assert!(minefield.reveal(&mut rng, 4, 4).unwrap());
+//! ```
+//!
+//! The game's window is in the binary, built with the feature `gui`. Built
+//! for WebAssembly, the library also has the game for the browser, in [`web`].
+
+pub mod backend;
+mod bdd;
+pub mod grid;
+mod mine_distribution;
+pub mod minefield;
+pub mod neighbor_iter;
+pub mod notation;
+pub mod replay;
+mod sat;
+pub mod save;
+pub mod search;
+pub mod solver;
+pub mod timing;
+pub mod topology;
+pub mod verify;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
+
+pub use grid::Grid;
+pub use minefield::Minefield;
+pub use neighbor_iter::NeighborIterable;
+pub use solver::PartialSolution;
@@ -1,9 +1,5 @@
-mod grid;
-mod minefield;
-mod neighbor_iter;
+mod board;
 mod right_clickable;
-mod search;
-mod solver;
 
 use iced::{
     executor,
@@ -11,10 +7,20 @@ use iced::{
     Application,
 };
 use iced_native::Theme;
-use minefield::Minefield;
+use minefield::{GameError, Minefield};
+use mineswapper::{
+    backend, grid, minefield, replay, save, solver, topology, verify, NeighborIterable,
+};
+use replay::{Action, Recording, ReplayError};
 use right_clickable::RightClickable;
+use save::SavedGame;
+use solver::{Hint, Progress};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use strum_macros;
 
 thread_local!(
     static FLAG: svg::Handle =
@@ -32,7 +38,7 @@ enum DifficultyLevels {
 }
 
 impl DifficultyLevels {
-    fn rows(self) -> u8 {
+    fn rows(self) -> u16 {
         match self {
             Self::Beginner => 9,
             Self::Intermediate => 16,
@@ -40,7 +46,7 @@ impl DifficultyLevels {
         }
     }
 
-    fn cols(self) -> u8 {
+    fn cols(self) -> u16 {
         match self {
             Self::Beginner => 9,
             Self::Intermediate => 16,
@@ -48,7 +54,7 @@ impl DifficultyLevels {
         }
     }
 
-    fn mines(self) -> u16 {
+    fn mines(self) -> u32 {
         match self {
             Self::Beginner => 10,
             Self::Intermediate => 40,
@@ -59,35 +65,40 @@ impl DifficultyLevels {
 
 #[derive(Copy, Clone)]
 struct Settings {
-    width: u8,
-    height: u8,
-    mine_count: u16,
+    width: u16,
+    height: u16,
+    mine_count: u32,
+    /// Name of the topology of the board, as given to topology::by_name().
+    topology: &'static str,
 }
 
 impl Settings {
-    fn new(width: u8, height: u8, mine_count: u16) -> Self {
+    fn new(width: u16, height: u16, mine_count: u32, topology: &'static str) -> Self {
         let mut new = Settings {
             width: 0,
             height: 0,
             mine_count: 0,
+            topology,
         };
         new.update(width, height, mine_count);
 
         new
     }
 
-    fn max_mines(self) -> u16 {
-        self.height as u16 * self.width as u16 - 1
+    fn max_mines(self) -> u32 {
+        self.height as u32 * self.width as u32 - 1
     }
 
-    fn update(&mut self, width: u8, height: u8, mine_count: u16) {
+    fn update(&mut self, width: u16, height: u16, mine_count: u32) {
         self.width = width;
         self.height = height;
 
         self.mine_count = std::cmp::min(mine_count, self.max_mines());
     }
 
-    fn view(&self) -> iced::Element<Message> {
+    /// The controls to start a new game, or to resume the saved one, if
+    /// "can_resume".
+    fn view(&self, can_resume: bool) -> iced::Element<'_, Message> {
         let selected = {
             let mut selected = None;
             for level in <DifficultyLevels as strum::IntoEnumIterator>::iter() {
@@ -129,6 +140,23 @@ impl Settings {
                 preset,
             ));
 
+        // In columns of 4, the shapes of the tiles then the neighborhoods:
+        let mut topologies = widget::Row::new().spacing(10);
+        for chunk in topology::all().chunks(4) {
+            let mut column = widget::Column::new();
+            for topology in chunk {
+                let name = topology.name();
+                let label = name[..1].to_uppercase() + &name[1..];
+                column = column.push(widget::Radio::new(
+                    label,
+                    name,
+                    Some(self.topology),
+                    Message::ChooseTopology,
+                ));
+            }
+            topologies = topologies.push(column);
+        }
+
         let labels = widget::Column::new()
             .push(widget::Text::new("Rows:"))
             .push(widget::Text::new("Columns:"))
@@ -141,28 +169,22 @@ impl Settings {
         let max_mines = self.max_mines();
 
         let sliders = widget::Column::new()
-            .push(
-                widget::Slider::new(2..=255, self.height, move |height| {
-                    Message::DefineSettings {
-                        width,
-                        height,
-                        mine_count,
-                        apply: false,
-                    }
-                })
-                .on_release(Message::ApplySettings),
-            )
-            .push(
-                widget::Slider::new(2..=255, self.width as u8, move |width| {
-                    Message::DefineSettings {
-                        width,
-                        height,
-                        mine_count,
-                        apply: false,
-                    }
-                })
-                .on_release(Message::ApplySettings),
-            )
+            .push(side_slider(self.height, move |height| {
+                Message::DefineSettings {
+                    width,
+                    height,
+                    mine_count,
+                    apply: false,
+                }
+            }))
+            .push(side_slider(self.width, move |width| {
+                Message::DefineSettings {
+                    width,
+                    height,
+                    mine_count,
+                    apply: false,
+                }
+            }))
             .push(
                 widget::Slider::new(1..=max_mines, self.mine_count, move |mine_count| {
                     Message::DefineSettings {
@@ -183,19 +205,45 @@ impl Settings {
                 "{} mines in {} cells, {:3.1} %",
                 mine_count,
                 max_mines + 1,
-                (100 * mine_count) as f32 / (max_mines + 1) as f32
+                100.0 * mine_count as f32 / (max_mines + 1) as f32
             )));
 
-        widget::Row::new()
+        let mut row = widget::Row::new()
             .push(presets)
+            .push(topologies)
             .push(labels)
             .push(sliders)
             .push(descriptions)
-            .spacing(10)
-            .into()
+            .spacing(10);
+        if can_resume {
+            row = row
+                .push(widget::Button::new(widget::Text::new("Resume")).on_press(Message::Resume));
+        }
+        row.into()
     }
 }
 
+/// The most rows or columns the window offers. The library takes boards up
+/// to 65535 tiles a side, but the window draws every tile of the board, and
+/// hangs long before that.
+const MAX_SIDE: u16 = 300;
+
+/// A slider for the number of rows or columns, from 2 to MAX_SIDE, on a
+/// logarithmic scale so that the usual sizes don't get lost among the
+/// largest ones.
+fn side_slider<'a>(
+    value: u16,
+    on_change: impl Fn(u16) -> Message + 'a,
+) -> iced::Element<'a, Message> {
+    let range = 2f64.ln()..=f64::from(MAX_SIDE).ln();
+    widget::Slider::new(range, f64::from(value).ln(), move |position| {
+        on_change(position.exp().round() as u16)
+    })
+    .step(0.001)
+    .on_release(Message::ApplySettings)
+    .into()
+}
+
 #[derive(Copy, Clone)]
 struct RunningView {
     start_time: Instant,
@@ -204,6 +252,7 @@ struct RunningView {
 fn status_display<'a>(
     minefield: &minefield::Minefield,
     display_elements: impl Iterator<Item = iced::Element<'a, Message>>,
+    extra_buttons: impl Iterator<Item = widget::Button<'a, Message>>,
 ) -> iced::Element<'a, Message> {
     let mut info = widget::Column::new()
         .spacing(10)
@@ -223,9 +272,14 @@ fn status_display<'a>(
         info = info.push(e)
     }
 
-    let button = widget::Button::new(widget::Text::new("Restart")).on_press(Message::Restart);
+    let mut buttons = widget::Column::new()
+        .spacing(10)
+        .push(widget::Button::new(widget::Text::new("Restart")).on_press(Message::Restart));
+    for button in extra_buttons {
+        buttons = buttons.push(button);
+    }
 
-    widget::Row::new().push(info).push(button).into()
+    widget::Row::new().push(info).push(buttons).into()
 }
 
 impl RunningView {
@@ -235,13 +289,65 @@ impl RunningView {
         }
     }
 
-    fn view(&self, minefield: &minefield::Minefield) -> iced::Element<Message> {
+    fn view(
+        &self,
+        minefield: &minefield::Minefield,
+        hint: Option<&Hint>,
+        show_probabilities: bool,
+        pending: Option<&Progress>,
+    ) -> iced::Element<'_, Message> {
         let delta = Instant::now() - self.start_time;
 
+        if let Some(progress) = pending {
+            return status_display(
+                minefield,
+                std::iter::once(
+                    widget::Text::new(format!(
+                        "Solving... {} components solved, {} alternatives found.",
+                        progress.components_solved.load(Ordering::Relaxed),
+                        progress.alternatives_found.load(Ordering::Relaxed)
+                    ))
+                    .into(),
+                ),
+                std::iter::once(
+                    widget::Button::new(widget::Text::new("Cancel")).on_press(Message::Cancel),
+                ),
+            );
+        }
+
+        let hint = hint.map(|hint| {
+            let cell = if hint.is_mine {
+                "the red cell is a mine"
+            } else {
+                "the green cell is safe"
+            };
+            widget::Text::new(if hint.clues.is_empty() {
+                format!("The number of mines left shows {}.", cell)
+            } else if hint.uses_mine_count {
+                format!(
+                    "With the number of mines left, the highlighted clues show {}.",
+                    cell
+                )
+            } else {
+                format!("The highlighted clues show {}.", cell)
+            })
+            .into()
+        });
+
         status_display(
             minefield,
-            [widget::Text::new(format!("Ellapsed time: {} seconds", delta.as_secs())).into()]
-                .into_iter(),
+            [
+                widget::Text::new(format!("Ellapsed time: {} seconds", delta.as_secs())).into(),
+                widget::Checkbox::new(
+                    "Show mine probabilities",
+                    show_probabilities,
+                    Message::ShowProbabilities,
+                )
+                .into(),
+            ]
+            .into_iter()
+            .chain(hint),
+            std::iter::once(widget::Button::new(widget::Text::new("Hint")).on_press(Message::Hint)),
         )
     }
 }
@@ -259,7 +365,19 @@ impl EndGameView {
         Self { game_duration, won }
     }
 
-    fn view(&self, minefield: &minefield::Minefield) -> iced::Element<Message> {
+    fn view(&self, minefield: &minefield::Minefield) -> iced::Element<'_, Message> {
+        let explanation = minefield.loss_explanation().map(|loss| {
+            widget::Text::new(if loss.clues.is_empty() {
+                "There was no room anywhere else for the mines left."
+            } else if loss.uses_mine_count {
+                "With the number of mines left, the highlighted clues \
+                 show the opened cell had to be a mine."
+            } else {
+                "The highlighted clues show the opened cell had to be a mine."
+            })
+            .into()
+        });
+
         status_display(
             minefield,
             [
@@ -276,9 +394,25 @@ impl EndGameView {
                 .size(40)
                 .into(),
             ]
-            .into_iter(),
+            .into_iter()
+            .chain(explanation),
+            std::iter::empty(),
         )
     }
+
+    /// The tiles proving the player lost, to be highlighted on the board.
+    fn highlighted_tiles(minefield: &minefield::Minefield) -> HashMap<(u16, u16), FilledStyle> {
+        let mut highlighted = HashMap::new();
+        if let Some(loss) = minefield.loss_explanation() {
+            for key in &loss.clues {
+                highlighted.insert(*key, FilledStyle::HIGHLIGHTED);
+            }
+            for key in &loss.revealed {
+                highlighted.insert(*key, FilledStyle::MINE);
+            }
+        }
+        highlighted
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -291,44 +425,70 @@ enum GameState {
 #[derive(Debug, Clone)]
 enum Message {
     DefineSettings {
-        width: u8,
-        height: u8,
-        mine_count: u16,
+        width: u16,
+        height: u16,
+        mine_count: u32,
         apply: bool,
     },
     ApplySettings,
+    ChooseTopology(&'static str),
     Restart,
     Tick,
-    Reveal(u8, u8),
-    Mark(u8, u8),
+    Reveal(u16, u16),
+    /// The work with the given id was done by the solver in the background.
+    Solved(u64, Solution),
+    Cancel,
+    DismissError,
+    Mark(u16, u16),
+    Hint,
+    ShowProbabilities(bool),
+    /// Time to make the next move of the game being replayed, if it is due.
+    ReplayStep,
+    Resume,
+    CloseRequested,
 }
 
-struct RevealedStyle;
-
-impl RevealedStyle {
-    const WHITE_BG: Option<iced::Background> = Some(iced::Background::Color(iced::Color::WHITE));
-}
+/// A button with a solid background that doesn't change on interaction.
+#[derive(Copy, Clone)]
+struct FilledStyle(iced::Color);
+
+impl FilledStyle {
+    const REVEALED: Self = Self(iced::Color::WHITE);
+    const HIGHLIGHTED: Self = Self(iced::Color::from_rgb(1.0, 0.8, 0.4));
+    const SAFE: Self = Self(iced::Color::from_rgb(0.6, 0.9, 0.6));
+    const MINE: Self = Self(iced::Color::from_rgb(1.0, 0.5, 0.5));
+
+    /// Tints between safe and mine, according to the probability of a mine.
+    fn probability(prob: f64) -> Self {
+        let prob = prob as f32;
+        let lerp = |safe: f32, mine: f32| safe + (mine - safe) * prob;
+        let (safe, mine) = (Self::SAFE.0, Self::MINE.0);
+        Self(iced::Color::from_rgb(
+            lerp(safe.r, mine.r),
+            lerp(safe.g, mine.g),
+            lerp(safe.b, mine.b),
+        ))
+    }
 
-impl widget::button::StyleSheet for RevealedStyle {
-    fn active(&self, _: &Theme) -> widget::button::Appearance {
+    fn appearance(&self) -> widget::button::Appearance {
         widget::button::Appearance {
-            background: Self::WHITE_BG,
+            background: Some(iced::Background::Color(self.0)),
             ..widget::button::Appearance::default()
         }
     }
+}
+
+impl widget::button::StyleSheet for FilledStyle {
+    fn active(&self, _: &Theme) -> widget::button::Appearance {
+        self.appearance()
+    }
 
     fn hovered(&self, _: &Theme) -> widget::button::Appearance {
-        widget::button::Appearance {
-            background: Self::WHITE_BG,
-            ..widget::button::Appearance::default()
-        }
+        self.appearance()
     }
 
     fn pressed(&self, _: &Theme) -> widget::button::Appearance {
-        widget::button::Appearance {
-            background: Self::WHITE_BG,
-            ..widget::button::Appearance::default()
-        }
+        self.appearance()
     }
 
     type Style = Theme;
@@ -347,13 +507,32 @@ fn number_color(clue: u8) -> iced_native::Color {
         6 => Color::from_rgb8(0x00, 0x80, 0x80),
         7 => Color::BLACK,
         8 => Color::from_rgb8(0x80, 0x80, 0x80),
-        _ => {
-            panic!("Invalid clue value: {}", clue);
-        }
+        // For the tiles with more than 8 neighbors:
+        9 => Color::from_rgb8(0x80, 0x00, 0x80),
+        10 => Color::from_rgb8(0x80, 0x80, 0x00),
+        11 => Color::from_rgb8(0xff, 0x80, 0x00),
+        12 => Color::from_rgb8(0x00, 0x80, 0xff),
+        // Only the 5x5 neighborhood goes above, and rarely:
+        _ => Color::from_rgb8(0x40, 0x40, 0x40),
     }
 }
 
-fn create_button(tile: &minefield::Tile, exposed: bool) -> widget::Button<Message> {
+fn create_button(
+    tile: &minefield::Tile,
+    exposed: bool,
+    highlight: Option<FilledStyle>,
+) -> widget::Button<'_, Message> {
+    let button = create_tile_button(tile, exposed);
+    if let Some(style) = highlight {
+        button.style(<Theme as widget::button::StyleSheet>::Style::Custom(
+            Box::new(style),
+        ))
+    } else {
+        button
+    }
+}
+
+fn create_tile_button(tile: &minefield::Tile, exposed: bool) -> widget::Button<'_, Message> {
     if exposed {
         match tile {
             minefield::Tile::Hidden(minefield::Content::Mine, minefield::UserMarking::None) => {
@@ -385,27 +564,171 @@ fn create_button(tile: &minefield::Tile, exposed: bool) -> widget::Button<Messag
                 .style(number_color(*clue)),
         )
         .style(<Theme as widget::button::StyleSheet>::Style::Custom(
-            Box::new(RevealedStyle),
+            Box::new(FilledStyle::REVEALED),
         )),
     }
 }
 
+/// The board as a grid of buttons, for the topologies with square tiles.
+fn tile_buttons<'a>(
+    minefield: &'a Minefield,
+    exposed: bool,
+    highlighted: &HashMap<(u16, u16), FilledStyle>,
+) -> iced::Element<'a, Message> {
+    let mut mf = widget::Column::new().spacing(1);
+    for (row, tiles) in (0u16..).zip(minefield.grid.rows()) {
+        let mut view_row = widget::Row::new().spacing(1);
+        for (col, tile) in (0u16..).zip(tiles.iter()) {
+            view_row = view_row.push(
+                RightClickable::new(
+                    create_button(tile, exposed, highlighted.get(&(row, col)).copied())
+                        .width(iced::Length::Fixed(29.0))
+                        .height(iced::Length::Fixed(29.0))
+                        .on_press(Message::Reveal(row, col)),
+                )
+                .on_right_click(Message::Mark(row, col)),
+            );
+        }
+        mf = mf.push(view_row);
+    }
+    mf.into()
+}
+
+/// The tiles to highlight when showing a hint to the player.
+fn hint_tiles(hint: Option<&Hint>) -> HashMap<(u16, u16), FilledStyle> {
+    let mut highlighted = HashMap::new();
+    if let Some(hint) = hint {
+        for key in &hint.clues {
+            highlighted.insert(*key, FilledStyle::HIGHLIGHTED);
+        }
+        highlighted.insert(
+            hint.cell,
+            if hint.is_mine {
+                FilledStyle::MINE
+            } else {
+                FilledStyle::SAFE
+            },
+        );
+    }
+    highlighted
+}
+
+/// The outcome of a move solved in the background.
+#[derive(Clone)]
+struct MoveResult {
+    minefield: Minefield,
+    rng: rand_xoshiro::Xoshiro256StarStar,
+    survived: bool,
+    probabilities: Option<grid::Grid<f64, u16, ()>>,
+}
+
+/// What the solver is asked in the background.
+#[derive(Copy, Clone)]
+enum Work {
+    /// A move, and when it was made, to be recorded once solved.
+    Move(Action, Duration),
+    Hint,
+    Probabilities,
+}
+
+/// What the solver found in the background.
+#[derive(Clone)]
+enum Outcome {
+    Move(Box<MoveResult>),
+    Hint(Option<Hint>),
+    Probabilities(grid::Grid<f64, u16, ()>),
+}
+
+/// What the solver found, or why it failed, as sent in a message. Messages
+/// must be cloneable, but this one is delivered once, and never cloned.
+#[derive(Clone)]
+struct Solution(Arc<Result<Outcome, String>>);
+
+impl std::fmt::Debug for Solution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Solution")
+    }
+}
+
+/// How long the solver may work before giving up, unless set in seconds by
+/// the environment variable MINESWAPPER_TIME_LIMIT, where 0 means no limit.
+const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(60);
+
+fn time_limit() -> Option<Duration> {
+    match std::env::var("MINESWAPPER_TIME_LIMIT") {
+        Ok(secs) => match secs.parse() {
+            Ok(0) => None,
+            Ok(secs) => Some(Duration::from_secs(secs)),
+            Err(_) => {
+                println!("Invalid time limit \"{}\", using the default.", secs);
+                Some(DEFAULT_TIME_LIMIT)
+            }
+        },
+        Err(_) => Some(DEFAULT_TIME_LIMIT),
+    }
+}
+
+/// Work being done by the solver in the background.
+///
+/// The solver works on copies of the minefield and the random generator, so
+/// the work is rolled back simply by dropping this.
+struct PendingWork {
+    id: u64,
+    work: Work,
+    progress: Arc<Progress>,
+    /// The state to go back to if the work is cancelled.
+    previous_state: GameState,
+}
+
+impl Drop for PendingWork {
+    fn drop(&mut self) {
+        // Don't waste the CPU on a result that will not be used:
+        self.progress.cancel();
+    }
+}
+
 struct Minesweeper {
     minefield: Minefield,
     rng: rand_xoshiro::Xoshiro256StarStar,
     state: GameState,
+    hint: Option<Hint>,
+    /// Probability of each tile holding a mine, if being shown.
+    probabilities: Option<grid::Grid<f64, u16, ()>>,
+    pending: Option<PendingWork>,
+    /// How many times the solver was started in the background, which
+    /// gives each work its id.
+    work_count: u64,
+    /// How long the solver may work before giving up, if limited.
+    time_limit: Option<Duration>,
+    /// The seed of the random generator and the moves made so far, to
+    /// reproduce the game.
+    recording: Recording,
+    /// When the game was created, which the moves are timed from.
+    created: Instant,
+    /// The game being played again, if in replay mode, and how many of its
+    /// moves were already made.
+    replaying: Option<(Recording, usize)>,
+    /// The game saved when the application was last closed, to be resumed
+    /// instead of starting a new one.
+    saved: Option<SavedGame>,
+    /// What went wrong, if the last action failed.
+    error: Option<String>,
+}
+
+/// Where the game in progress is saved when the application is closed.
+fn save_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("mineswapper").join("saved_game"))
 }
 
 impl Minesweeper {
-    fn new(settings: Settings) -> Self {
+    fn new(settings: Settings) -> Result<Self, ReplayError> {
         use hex::FromHex;
-        use rand_core::SeedableRng;
         use std::env;
 
-        let rng_seed = if let Some(Ok(seed)) = env::args_os().nth(1).and_then(|arg| {
-            arg.to_str()
-                .map(|valid_str| <[u8; 32]>::from_hex(valid_str))
-        }) {
+        let rng_seed = if let Some(Ok(seed)) = env::args_os()
+            .nth(1)
+            .and_then(|arg| arg.to_str().map(<[u8; 32]>::from_hex))
+        {
             println!("Using provided seed.");
 
             seed
@@ -417,17 +740,341 @@ impl Minesweeper {
             seed
         };
 
-        let mut rng = rand_xoshiro::Xoshiro256StarStar::from_seed(rng_seed);
+        // The solver backend can be chosen by name from the environment:
+        let backend = match env::var("MINESWAPPER_BACKEND") {
+            Ok(name) => backend::by_name(&name).unwrap_or_else(|| {
+                println!("Unknown solver backend \"{}\", using the default.", name);
+                Arc::new(backend::Diagram)
+            }),
+            Err(_) => Arc::new(backend::Diagram),
+        };
 
-        Self {
-            minefield: Minefield::create_random(
-                settings.width,
-                settings.height,
-                settings.mine_count,
-                &mut rng,
-            ),
+        Self::start(Recording::new(
+            rng_seed,
+            settings.width,
+            settings.height,
+            settings.mine_count,
+            settings.topology,
+            backend.name(),
+        ))
+    }
+
+    /// Creates the game the recording starts from, with no moves made.
+    fn start(recording: Recording) -> Result<Self, ReplayError> {
+        let (mut minefield, rng) = recording.start()?;
+        minefield.set_verification(verify::is_enabled());
+        let settings = Settings::new(
+            recording.width,
+            recording.height,
+            recording.mine_count,
+            minefield.topology().name(),
+        );
+
+        Ok(Self {
+            minefield,
             rng,
             state: GameState::BeforeStarted(settings),
+            hint: None,
+            probabilities: None,
+            pending: None,
+            work_count: 0,
+            time_limit: time_limit(),
+            recording: Recording {
+                steps: Vec::new(),
+                ..recording
+            },
+            created: Instant::now(),
+            replaying: None,
+            saved: None,
+            error: None,
+        })
+    }
+
+    /// Goes on with the saved game, as if the application was never closed.
+    fn resume(&mut self, saved: SavedGame) {
+        self.minefield = saved.minefield;
+        self.minefield.set_verification(verify::is_enabled());
+        self.rng = saved.rng;
+        self.state = GameState::Running(RunningView {
+            start_time: Instant::now() - saved.elapsed,
+        });
+        // The next moves are timed as if made right after the last one:
+        let last_move = saved.recording.steps.last().map(|step| step.time);
+        self.created = Instant::now() - last_move.unwrap_or_default();
+        self.recording = saved.recording;
+
+        // It will be saved again on close, if still in progress:
+        if let Some(path) = save_path() {
+            if let Err(err) = std::fs::remove_file(path) {
+                eprintln!("Can't remove the saved game: {}", err);
+            }
+        }
+    }
+
+    /// Saves the game in progress, to be resumed the next time the
+    /// application starts.
+    fn autosave(&self, elapsed: Duration) {
+        let saved = SavedGame {
+            minefield: self.minefield.clone(),
+            rng: self.rng.clone(),
+            elapsed,
+            recording: self.recording.clone(),
+        };
+        match save_path() {
+            Some(path) => {
+                if let Err(err) = saved.save(&path) {
+                    eprintln!("Can't save the game: {}", err);
+                }
+            }
+            None => eprintln!("Nowhere to save the game."),
+        }
+    }
+
+    /// Plays again the game recorded in the file, making each move when it
+    /// was made originally.
+    fn replay(path: &std::ffi::OsStr) -> Result<Self, ReplayError> {
+        let file = std::fs::File::open(path)?;
+        let recording = Recording::read_from(std::io::BufReader::new(file))?;
+
+        let mut new = Self::start(recording.clone())?;
+        new.replaying = Some((recording, 0));
+        Ok(new)
+    }
+
+    /// Starts a new game with the given settings, or keeps the current one
+    /// and shows the error if it can't be created.
+    fn restart(&mut self, settings: Settings) {
+        match Self::new(settings) {
+            // The ids go on from the abandoned game, so that a result of
+            // its solver can't pass for one of the new game:
+            Ok(new) => {
+                *self = Self {
+                    work_count: self.work_count,
+                    ..new
+                }
+            }
+            Err(err) => self.show_error(&err),
+        }
+    }
+
+    fn show_error(&mut self, err: &dyn std::fmt::Display) {
+        let error = err.to_string();
+        eprintln!(
+            "Error: {} (seed: {})",
+            error,
+            hex::encode(self.recording.seed)
+        );
+        self.error = Some(error);
+    }
+
+    /// The controls shown in place of the others when something went wrong.
+    fn error_view(&self, error: &str) -> iced::Element<'_, Message> {
+        status_display(
+            &self.minefield,
+            [
+                widget::Text::new("Something went wrong!").size(30).into(),
+                widget::Text::new(error.to_owned()).into(),
+                widget::Text::new(format!("Seed: {}", hex::encode(self.recording.seed))).into(),
+            ]
+            .into_iter(),
+            std::iter::once(
+                widget::Button::new(widget::Text::new("Dismiss")).on_press(Message::DismissError),
+            ),
+        )
+    }
+
+    /// Adds the move to the recording, and saves it to the file named by the
+    /// environment variable MINESWAPPER_RECORD, if set. When replaying,
+    /// checks the move left the board as it was recorded.
+    fn record(&mut self, time: Duration, action: Action) {
+        self.recording.record(time, action, &self.minefield);
+        if let Err(err) = self.save_recording() {
+            self.show_error(&err);
+        }
+
+        let step = self.recording.steps.len() - 1;
+        if let Some(Err(err)) = self
+            .replaying
+            .as_ref()
+            .map(|(replaying, _)| replaying.check(step, &self.minefield))
+        {
+            self.replaying = None;
+            self.show_error(&err);
+        }
+    }
+
+    fn save_recording(&self) -> Result<(), ReplayError> {
+        if let Some(path) = std::env::var_os("MINESWAPPER_RECORD") {
+            let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+            self.recording.write_to(&mut file)?;
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    fn reveal(&mut self, row: u16, col: u16) -> iced::Command<Message> {
+        let previous_state = self.state;
+        if let GameState::BeforeStarted(_) = self.state {
+            self.state = GameState::Running(RunningView::new());
+        }
+
+        if let GameState::Running(_) = self.state {
+            self.hint = None;
+            return self.reveal_in_background(row, col, previous_state);
+        }
+        iced::Command::none()
+    }
+
+    fn mark(&mut self, row: u16, col: u16) {
+        if let GameState::BeforeStarted(_) | GameState::Running(_) = self.state {
+            self.hint = None;
+            match self.minefield.switch_mark(row, col) {
+                Ok(()) => self.record(self.created.elapsed(), Action::Mark(row, col)),
+                Err(err) => self.show_error(&err),
+            }
+        }
+    }
+
+    /// Makes the next move of the game being replayed, if it is time. Once
+    /// every move was made, the player takes over.
+    fn replay_step(&mut self) -> iced::Command<Message> {
+        let (recording, next) = match &mut self.replaying {
+            Some(replaying) if self.pending.is_none() => replaying,
+            _ => return iced::Command::none(),
+        };
+        let step = match recording.steps.get(*next) {
+            Some(step) if step.time <= self.created.elapsed() => step.action,
+            Some(_) => return iced::Command::none(),
+            None => {
+                self.replaying = None;
+                return iced::Command::none();
+            }
+        };
+
+        *next += 1;
+        match step {
+            Action::Reveal(row, col) => self.reveal(row, col),
+            Action::Mark(row, col) => {
+                self.mark(row, col);
+                iced::Command::none()
+            }
+        }
+    }
+
+    /// Starts the solver in a background thread, on copies of the
+    /// minefield and the random generator, so that the interface stays
+    /// responsive however long it takes. Past the time limit, the solver is
+    /// cancelled and the work fails.
+    fn solve_in_background(
+        &mut self,
+        work: Work,
+        previous_state: GameState,
+        solve: impl FnOnce(Minefield, rand_xoshiro::Xoshiro256StarStar) -> Result<Outcome, GameError>
+            + Send
+            + 'static,
+    ) -> iced::Command<Message> {
+        let progress = Arc::new(Progress::default());
+        let mut minefield = self.minefield.clone();
+        minefield.track_progress(progress.clone());
+        let rng = self.rng.clone();
+
+        let time_limit = self.time_limit;
+        let watched = progress.clone();
+        let solving = async move {
+            let mut task = tokio::task::spawn_blocking(move || solve(minefield, rng));
+            let result = match time_limit {
+                Some(limit) => match tokio::time::timeout(limit, &mut task).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        // Wait for the solver to stop, so that it doesn't
+                        // outlive the work:
+                        watched.cancel();
+                        let _ = task.await;
+                        return Err(format!("the solver gave up after {} s", limit.as_secs()));
+                    }
+                },
+                None => task.await,
+            };
+            match result {
+                Ok(result) => result.map_err(|err| err.to_string()),
+                // The solver panicked:
+                Err(_) => Err("the solver stopped unexpectedly".to_owned()),
+            }
+        };
+
+        self.work_count += 1;
+        let id = self.work_count;
+        self.pending = Some(PendingWork {
+            id,
+            work,
+            progress,
+            previous_state,
+        });
+
+        iced::Command::perform(solving, move |result| {
+            Message::Solved(id, Solution(Arc::new(result)))
+        })
+    }
+
+    fn reveal_in_background(
+        &mut self,
+        row: u16,
+        col: u16,
+        previous_state: GameState,
+    ) -> iced::Command<Message> {
+        let with_probabilities = self.probabilities.is_some();
+        let work = Work::Move(Action::Reveal(row, col), self.created.elapsed());
+        self.solve_in_background(work, previous_state, move |mut minefield, mut rng| {
+            let survived = minefield.reveal(&mut rng, row, col)?;
+            let probabilities =
+                (survived && with_probabilities).then(|| minefield.mine_probabilities());
+            Ok(Outcome::Move(Box::new(MoveResult {
+                minefield,
+                rng,
+                survived,
+                probabilities,
+            })))
+        })
+    }
+
+    fn hint_in_background(&mut self) -> iced::Command<Message> {
+        self.solve_in_background(Work::Hint, self.state, |mut minefield, _| {
+            Ok(Outcome::Hint(minefield.hint()))
+        })
+    }
+
+    fn probabilities_in_background(&mut self) -> iced::Command<Message> {
+        self.solve_in_background(Work::Probabilities, self.state, |mut minefield, _| {
+            Ok(Outcome::Probabilities(minefield.mine_probabilities()))
+        })
+    }
+
+    /// Applies the move solved in the background.
+    fn apply_move(&mut self, result: MoveResult, action: Action, time: Duration) {
+        self.minefield = result.minefield;
+        // The progress of the move is cancelled along with it, which
+        // would stop the solver from then on:
+        self.minefield.track_progress(Arc::default());
+        self.rng = result.rng;
+        self.record(time, action);
+
+        if let GameState::Running(running) = self.state {
+            let has_won = result.survived && self.minefield.is_all_revealed();
+            if !result.survived || has_won {
+                self.state = GameState::Finished(EndGameView::new(running.start_time, has_won));
+                self.probabilities = None;
+            } else if self.probabilities.is_some() {
+                self.probabilities = result.probabilities;
+            }
+        }
+    }
+
+    /// Goes back to before the work started, when it was cancelled or failed.
+    fn roll_back(&mut self, pending: &PendingWork) {
+        self.state = pending.previous_state;
+        // The replay can't go on without the move:
+        if let Work::Move(..) = pending.work {
+            self.replaying = None;
         }
     }
 }
@@ -439,18 +1086,42 @@ impl Application for Minesweeper {
 
     fn new(_flags: ()) -> (Self, iced::Command<Message>) {
         const DEFAULT: DifficultyLevels = DifficultyLevels::Expert;
-        (
-            Self::new(Settings::new(
-                DEFAULT.cols(),
-                DEFAULT.rows(),
-                DEFAULT.mines(),
-            )),
-            iced::Command::none(),
-        )
+        let mut new = Self::new(Settings::new(
+            DEFAULT.cols(),
+            DEFAULT.rows(),
+            DEFAULT.mines(),
+            "square",
+        ))
+        .expect("The default settings must be valid");
+
+        // A recorded game to play again can be given in the environment:
+        if let Some(path) = std::env::var_os("MINESWAPPER_REPLAY") {
+            match Self::replay(&path) {
+                Ok(replay) => new = replay,
+                Err(err) => new.show_error(&err),
+            }
+        }
+
+        new.saved =
+            save_path().filter(|path| path.exists()).and_then(|path| {
+                match SavedGame::load(&path) {
+                    Ok(saved) => Some(saved),
+                    Err(err) => {
+                        eprintln!("Can't load the saved game: {}", err);
+                        None
+                    }
+                }
+            });
+
+        (new, iced::Command::none())
     }
 
     fn title(&self) -> String {
-        String::from("Mineswapper")
+        if self.replaying.is_some() {
+            String::from("Mineswapper (replay)")
+        } else {
+            String::from("Mineswapper")
+        }
     }
 
     fn update(&mut self, message: Self::Message) -> iced::Command<Self::Message> {
@@ -461,89 +1132,183 @@ impl Application for Minesweeper {
                 mine_count,
                 apply,
             } => {
+                // The settings can only change before the game starts:
                 if let GameState::BeforeStarted(settings) = &mut self.state {
                     settings.update(width, height, mine_count);
                     if apply {
-                        *self = Minesweeper::new(*settings);
+                        let settings = *settings;
+                        self.restart(settings);
                     }
-                } else {
-                    panic!("We should only get settings message before started!");
                 }
             }
             Message::ApplySettings => {
-                if let GameState::BeforeStarted(settings) = &self.state {
-                    *self = Minesweeper::new(*settings);
-                } else {
-                    panic!("We should only get settings message before started!");
+                if let GameState::BeforeStarted(settings) = self.state {
+                    self.restart(settings);
+                }
+            }
+            Message::ChooseTopology(topology) => {
+                if let GameState::BeforeStarted(settings) = &mut self.state {
+                    settings.topology = topology;
+                    let settings = *settings;
+                    self.restart(settings);
                 }
             }
             Message::Restart => {
-                *self = Self::new(Settings::new(
+                self.restart(Settings::new(
                     self.minefield.grid.width(),
                     self.minefield.grid.height(),
                     self.minefield.mine_count,
+                    self.minefield.topology().name(),
                 ));
             }
-            Message::Reveal(row, col) => {
+            Message::DismissError => {
+                self.error = None;
+            }
+            // The player can't interfere with a replay:
+            Message::Reveal(..) | Message::Mark(..) if self.replaying.is_some() => {}
+            // Nothing else can change while a move is being solved:
+            Message::Reveal(..)
+            | Message::Mark(..)
+            | Message::Hint
+            | Message::ShowProbabilities(_)
+                if self.pending.is_some() => {}
+            Message::Resume => {
                 if let GameState::BeforeStarted(_) = self.state {
-                    self.state = GameState::Running(RunningView::new());
+                    if let Some(saved) = self.saved.take() {
+                        self.resume(saved);
+                    }
                 }
-
+            }
+            Message::CloseRequested => {
                 if let GameState::Running(running) = self.state {
-                    let has_lost = !self.minefield.reveal(&mut self.rng, row, col);
-                    let has_won = !has_lost && self.minefield.is_all_revealed();
-
-                    if has_lost || has_won {
-                        self.state =
-                            GameState::Finished(EndGameView::new(running.start_time, has_won));
+                    self.autosave(running.start_time.elapsed());
+                }
+                return iced::window::close();
+            }
+            Message::Reveal(row, col) => return self.reveal(row, col),
+            Message::ReplayStep => return self.replay_step(),
+            Message::Solved(id, Solution(result)) => {
+                if !matches!(&self.pending, Some(pending) if pending.id == id) {
+                    // A cancelled work.
+                    return iced::Command::none();
+                }
+                let pending = match self.pending.take() {
+                    Some(pending) => pending,
+                    None => return iced::Command::none(),
+                };
+                match Arc::unwrap_or_clone(result) {
+                    Ok(Outcome::Move(result)) => {
+                        if let Work::Move(action, time) = pending.work {
+                            self.apply_move(*result, action, time);
+                        }
+                    }
+                    Ok(Outcome::Hint(hint)) => self.hint = hint,
+                    Ok(Outcome::Probabilities(probabilities)) => {
+                        self.probabilities = Some(probabilities)
+                    }
+                    Err(err) => {
+                        self.roll_back(&pending);
+                        self.show_error(&err);
                     }
                 }
             }
-            Message::Mark(row, col) => match self.state {
-                GameState::BeforeStarted(_) | GameState::Running(_) => {
-                    self.minefield.switch_mark(row, col)
+            Message::Cancel => {
+                if let Some(pending) = self.pending.take() {
+                    self.roll_back(&pending);
                 }
-                _ => {}
-            },
+            }
+            Message::Mark(row, col) => self.mark(row, col),
+            Message::ShowProbabilities(show) => {
+                self.probabilities = None;
+                if let (GameState::Running(_), true) = (self.state, show) {
+                    return self.probabilities_in_background();
+                }
+            }
+            Message::Hint => {
+                if let GameState::Running(_) = self.state {
+                    return self.hint_in_background();
+                }
+            }
             _ => {}
         };
         iced::Command::none()
     }
 
     fn subscription(&self) -> iced::Subscription<Message> {
-        iced::time::every(std::time::Duration::from_millis(500)).map(|_| Message::Tick)
+        let mut subscriptions = vec![
+            iced::time::every(std::time::Duration::from_millis(500)).map(|_| Message::Tick),
+            // The game is saved before the window closes:
+            iced::subscription::events_with(|event, _| match event {
+                iced::Event::Window(iced::window::Event::CloseRequested) => {
+                    Some(Message::CloseRequested)
+                }
+                _ => None,
+            }),
+        ];
+        if self.replaying.is_some() {
+            subscriptions.push(
+                iced::time::every(std::time::Duration::from_millis(50))
+                    .map(|_| Message::ReplayStep),
+            );
+        }
+        iced::Subscription::batch(subscriptions)
     }
 
-    fn view(&self) -> iced::Element<Self::Message> {
+    fn view(&self) -> iced::Element<'_, Self::Message> {
         // Minefield
-        let mut mf = widget::Column::new().spacing(1);
-        for (row, tiles) in (0u16..).zip(self.minefield.grid.rows()) {
-            let mut view_row = widget::Row::new().spacing(1);
-            for (col, tile) in (0u16..).zip(tiles.iter()) {
-                view_row = view_row.push(
-                    RightClickable::new(
-                        create_button(tile, matches!(self.state, GameState::Finished(_)))
-                            .width(iced::Length::Fixed(29.0))
-                            .height(iced::Length::Fixed(29.0))
-                            .on_press(Message::Reveal(row as u8, col as u8)),
-                    )
-                    .on_right_click(Message::Mark(row as u8, col as u8)),
-                );
+        let mut highlighted = match self.state {
+            GameState::Finished(_) => EndGameView::highlighted_tiles(&self.minefield),
+            _ => hint_tiles(self.hint.as_ref()),
+        };
+
+        if let Some(probabilities) = &self.probabilities {
+            for (row, tiles) in (0u16..).zip(self.minefield.grid.rows()) {
+                for (col, tile) in (0u16..).zip(tiles.iter()) {
+                    if let minefield::Tile::Hidden(..) = tile {
+                        highlighted.entry((row, col)).or_insert_with(|| {
+                            FilledStyle::probability(*probabilities.get(row, col))
+                        });
+                    }
+                }
             }
-            mf = mf.push(view_row);
         }
 
+        let exposed = matches!(self.state, GameState::Finished(_));
+        let mf: iced::Element<'_, Message> = match self.minefield.topology().name() {
+            "hexagonal" | "triangular" => {
+                let board = board::Board {
+                    minefield: &self.minefield,
+                    exposed,
+                    highlighted,
+                };
+                let size = board.size();
+                widget::Canvas::new(board)
+                    .width(iced::Length::Fixed(size.width))
+                    .height(iced::Length::Fixed(size.height))
+                    .into()
+            }
+            _ => tile_buttons(&self.minefield, exposed, &highlighted),
+        };
+
         // Controls
-        let controls = widget::Container::new(match &self.state {
-            GameState::BeforeStarted(controls) => controls.view(),
-            GameState::Running(running) => running.view(&self.minefield),
-            GameState::Finished(end_game) => end_game.view(&self.minefield),
+        let controls = widget::Container::new(match (&self.state, &self.error) {
+            (_, Some(error)) => self.error_view(error),
+            (GameState::BeforeStarted(controls), None) => controls.view(self.saved.is_some()),
+            (GameState::Running(running), None) => running.view(
+                &self.minefield,
+                self.hint.as_ref(),
+                self.probabilities.is_some(),
+                self.pending.as_ref().map(|pending| &*pending.progress),
+            ),
+            (GameState::Finished(end_game), None) => end_game.view(&self.minefield),
         })
-        .height(iced::Length::Fixed(150.0))
+        .height(iced::Length::Fixed(180.0))
         .padding(20);
 
-        // Aligner
-        let aligner = widget::Container::new(mf)
+        // Aligner, scrolling the boards too large for the window:
+        let board = widget::Scrollable::new(mf)
+            .horizontal_scroll(widget::scrollable::Properties::default());
+        let aligner = widget::Container::new(board)
             .width(iced::Length::Fill)
             .height(iced::Length::Fill)
             .align_x(iced::alignment::Horizontal::Center)
@@ -558,6 +1323,8 @@ impl Application for Minesweeper {
 
 fn main() -> iced::Result {
     let settings = iced::Settings {
+        // To save the game first:
+        exit_on_close_request: false,
         ..Default::default()
     };
     Minesweeper::run(settings)
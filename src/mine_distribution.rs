@@ -0,0 +1,236 @@
+use rand::distributions::{Distribution, WeightedIndex};
+
+/// How the hidden mines can be distributed among independent graphs of
+/// constrained cells and the unconstrained cells left over.
+///
+/// Every quantity is the natural logarithm of a number of mine layouts,
+/// because the counts easily overflow any integer type on big boards.
+pub struct MineDistribution {
+    /// For each graph, the mine counts its alternatives can have, paired
+    /// with the logarithm of how many alternatives have that count.
+    graphs: Vec<Vec<(u32, f64)>>,
+    /// prefix[i][t] is the logarithm of the number of ways the first i
+    /// graphs can hold t mines together.
+    prefix: Vec<Vec<f64>>,
+    /// suffix[i][t] is the logarithm of the number of ways the graphs from
+    /// the i-th onwards can hold t mines together.
+    suffix: Vec<Vec<f64>>,
+    /// Weight of each total of mines in the graphs, including the ways the
+    /// remaining mines can be placed among the unconstrained cells.
+    totals: Vec<f64>,
+    hidden_mines: u32,
+    unconstrained_cells: u32,
+    ln_factorials: Vec<f64>,
+}
+
+impl MineDistribution {
+    pub fn new(graphs: Vec<Vec<(u32, f64)>>, hidden_mines: u32, unconstrained_cells: u32) -> Self {
+        let mut prefix = vec![vec![0.0]];
+        for graph in graphs.iter() {
+            prefix.push(add_graph(prefix.last().unwrap(), graph));
+        }
+
+        let mut suffix = vec![vec![0.0]];
+        for graph in graphs.iter().rev() {
+            suffix.push(add_graph(suffix.last().unwrap(), graph));
+        }
+        suffix.reverse();
+
+        let mut ret = Self {
+            graphs,
+            prefix,
+            suffix,
+            totals: Vec::new(),
+            hidden_mines,
+            unconstrained_cells,
+            ln_factorials: ln_factorials(unconstrained_cells),
+        };
+
+        ret.totals = ret
+            .prefix
+            .last()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .map(|(total, ways)| ways + ret.ln_left_over_ways(total))
+            .collect();
+
+        ret
+    }
+
+    /// Logarithm of the number of ways the mines not in the graphs can be
+    /// placed among the unconstrained cells, given the graphs hold "total".
+    fn ln_left_over_ways(&self, total: usize) -> f64 {
+        // Do we have enough remaining mines to satisfy this total,
+        // and enough unconstrained cells to fit the mines left over?
+        match (self.hidden_mines as usize).checked_sub(total) {
+            Some(left) if left <= self.unconstrained_cells as usize => {
+                self.ln_factorials[self.unconstrained_cells as usize]
+                    - self.ln_factorials[left]
+                    - self.ln_factorials[self.unconstrained_cells as usize - left]
+            }
+            _ => f64::NEG_INFINITY,
+        }
+    }
+
+    /// Logarithm of the number of consistent full board layouts.
+    pub fn ln_layouts(&self) -> f64 {
+        self.totals
+            .iter()
+            .copied()
+            .fold(f64::NEG_INFINITY, ln_add_exp)
+    }
+
+    /// Logarithm of the number of full board layouts where the graph at
+    /// "graph_idx" holds each of its possible mine counts, in the same order
+    /// the counts were given.
+    pub fn graph_weights(&self, graph_idx: usize) -> Vec<f64> {
+        // Ways all the other graphs can hold each total of mines:
+        let before = &self.prefix[graph_idx];
+        let after = &self.suffix[graph_idx + 1];
+        let mut others = vec![f64::NEG_INFINITY; before.len() + after.len() - 1];
+        for (a, ways_a) in before.iter().enumerate() {
+            for (b, ways_b) in after.iter().enumerate() {
+                others[a + b] = ln_add_exp(others[a + b], ways_a + ways_b);
+            }
+        }
+
+        self.graphs[graph_idx]
+            .iter()
+            .map(|(count, ways)| {
+                let rest = others
+                    .iter()
+                    .enumerate()
+                    .map(|(total, others_ways)| {
+                        others_ways + self.ln_left_over_ways(total + *count as usize)
+                    })
+                    .fold(f64::NEG_INFINITY, ln_add_exp);
+                ways + rest
+            })
+            .collect()
+    }
+
+    /// Logarithm of the number of full board layouts for each possible
+    /// number of mines left over to the unconstrained cells.
+    pub fn left_over_weights(&self) -> impl Iterator<Item = (u32, f64)> + '_ {
+        self.totals
+            .iter()
+            .enumerate()
+            .filter(|(_, ways)| **ways > f64::NEG_INFINITY)
+            .map(|(total, ways)| (self.hidden_mines - total as u32, *ways))
+    }
+
+    /// Randomly selects how many mines each graph holds, with the probability
+    /// of each choice proportional to the number of full board layouts
+    /// consistent with it. Returns None if there is no consistent layout, or
+    /// if some weight is not a number.
+    pub fn sample(&self, rng: &mut impl rand::Rng) -> Option<Vec<u32>> {
+        let mut total = sample_ln_weighted(rng, self.totals.iter().copied())?;
+
+        let mut counts = vec![0; self.graphs.len()];
+        for (i, graph) in self.graphs.iter().enumerate().rev() {
+            let prev = &self.prefix[i];
+            let choice = sample_ln_weighted(
+                rng,
+                graph
+                    .iter()
+                    .map(|(count, ways)| match total.checked_sub(*count as usize) {
+                        Some(rest) if rest < prev.len() => prev[rest] + ways,
+                        _ => f64::NEG_INFINITY,
+                    }),
+            )?;
+
+            counts[i] = graph[choice].0;
+            total -= counts[i] as usize;
+        }
+
+        Some(counts)
+    }
+}
+
+/// Adds one more graph to the ways a set of graphs can hold each total of mines.
+fn add_graph(ways: &[f64], graph: &[(u32, f64)]) -> Vec<f64> {
+    let max_count = graph.iter().map(|(count, _)| *count).max().unwrap_or(0);
+
+    let mut next = vec![f64::NEG_INFINITY; ways.len() + max_count as usize];
+    for (total, ways) in ways.iter().enumerate() {
+        for (count, graph_ways) in graph.iter() {
+            let slot = &mut next[total + *count as usize];
+            *slot = ln_add_exp(*slot, ways + graph_ways);
+        }
+    }
+    next
+}
+
+/// Computes ln(e^a + e^b) without leaving the log-space.
+pub fn ln_add_exp(a: f64, b: f64) -> f64 {
+    let (max, min) = if a > b { (a, b) } else { (b, a) };
+    if min == f64::NEG_INFINITY {
+        max
+    } else {
+        max + (min - max).exp().ln_1p()
+    }
+}
+
+/// Natural logarithm of the factorials from 0! up to n!.
+fn ln_factorials(n: u32) -> Vec<f64> {
+    let mut ret = Vec::with_capacity(n as usize + 1);
+    let mut acc = 0.0;
+    ret.push(acc);
+    for i in 1..=n {
+        acc += f64::from(i).ln();
+        ret.push(acc);
+    }
+    ret
+}
+
+/// Samples an index with probability proportional to e^weight, or returns
+/// None if none of the weights is positive, or some is not a number.
+fn sample_ln_weighted(
+    rng: &mut impl rand::Rng,
+    ln_weights: impl Iterator<Item = f64>,
+) -> Option<usize> {
+    let ln_weights: Vec<f64> = ln_weights.collect();
+    let max = ln_weights.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return None;
+    }
+
+    let weights = ln_weights.iter().map(|w| (w - max).exp());
+    WeightedIndex::new(weights)
+        .ok()
+        .map(|weights| weights.sample(rng))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::SeedableRng;
+
+    #[test]
+    fn samples_only_valid_weights() {
+        let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(0);
+        let mut sample =
+            |ln_weights: &[f64]| sample_ln_weighted(&mut rng, ln_weights.iter().copied());
+
+        assert_eq!(sample(&[f64::NEG_INFINITY, 0.0]), Some(1));
+        assert_eq!(sample(&[1e6, f64::NEG_INFINITY]), Some(0));
+        assert_eq!(sample(&[f64::NEG_INFINITY; 2]), None);
+        assert_eq!(sample(&[]), None);
+        assert_eq!(sample(&[0.0, f64::NAN]), None);
+        assert_eq!(sample(&[f64::INFINITY, 0.0]), None);
+    }
+
+    #[test]
+    fn has_no_layout_without_room_for_the_mines() {
+        let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(0);
+        // One graph holding 1 or 2 mines, and 3 unconstrained cells:
+        let graphs = vec![vec![(1, 0.0), (2, 2f64.ln())]];
+        let distribution = MineDistribution::new(graphs.clone(), 5, 3);
+        assert_eq!(distribution.sample(&mut rng), Some(vec![2]));
+
+        let distribution = MineDistribution::new(graphs, 6, 3);
+        assert_eq!(distribution.ln_layouts(), f64::NEG_INFINITY);
+        assert_eq!(distribution.sample(&mut rng), None);
+    }
+}
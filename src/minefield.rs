@@ -1,10 +1,18 @@
-use std::time::Instant;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
 use rand::seq;
+use serde::{Deserialize, Serialize};
 use super::neighbor_iter::NeighborIterable;
-use super::solver::PartialSolution;
+use super::backend::Backend;
+use super::topology::Topology;
+use super::solver::{Hint, LossExplanation, PartialSolution, Progress, SolverError};
 use super::grid;
+use super::timing;
+use super::notation::{Cell, Position};
+use super::verify;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub enum UserMarking
 {
     None,
@@ -12,28 +20,34 @@ pub enum UserMarking
     QuestionMark,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub enum Content
 {
     Empty,
     Mine
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub enum Tile {
     Hidden(Content, UserMarking),
     Revealed(u8)
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct MinefieldCounters {
-    pub flag_count: u16,
-    pub revealed_count: u16,
+    pub flag_count: u32,
+    pub revealed_count: u32,
 }
 
 impl grid::GridCounters<Tile> for MinefieldCounters {
-    fn notify_change(&mut self, from: &Tile, to: &Tile)
+    type Error = GameError;
+
+    fn notify_change(&mut self, from: &Tile, to: &Tile) -> Result<(), GameError>
     {
+        if let (Tile::Revealed(_), Tile::Revealed(_)) = (*from, *to) {
+            return Err(GameError::RevealedTwice);
+        }
+
         if let Tile::Hidden(_, UserMarking::Flag) = *from {
             self.flag_count -= 1;
         }
@@ -43,29 +57,84 @@ impl grid::GridCounters<Tile> for MinefieldCounters {
                 self.flag_count += 1;
             },
             Tile::Revealed(_) => {
-                assert!(!matches!(*from, Tile::Revealed(_)));
                 self.revealed_count += 1;
             },
             _ => ()
         }
+
+        Ok(())
+    }
+}
+
+/// Something went wrong with the game, that should never happen in a
+/// correct implementation.
+#[derive(Debug, Clone)]
+pub enum GameError {
+    /// The field was asked to have more mines than cells.
+    TooManyMines { mine_count: u32, cell_count: usize },
+    /// A tile was revealed more than once.
+    RevealedTwice,
+    /// The solver found the board inconsistent.
+    Solver(SolverError),
+    /// Something the game relies on doesn't hold.
+    Invariant(&'static str),
+    /// The solver disagrees with the brute force of the verify module, as
+    /// explained in the report.
+    Mismatch(String),
+}
+
+impl From<SolverError> for GameError {
+    fn from(err: SolverError) -> Self
+    {
+        GameError::Solver(err)
+    }
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            GameError::TooManyMines { mine_count, cell_count } => write!(f,
+                "{} mines don't fit in a field of {} cells", mine_count, cell_count),
+            GameError::RevealedTwice => write!(f, "a tile was revealed twice"),
+            GameError::Solver(err) => write!(f, "solver error: {}", err),
+            GameError::Invariant(what) => write!(f, "broken invariant: {}", what),
+            GameError::Mismatch(report) => write!(f,
+                "the solver disagrees with the brute force: {}", report),
+        }
     }
 }
 
+impl std::error::Error for GameError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>
+    {
+        match self {
+            GameError::Solver(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Minefield {
-    pub grid: grid::Grid<Tile, u8, MinefieldCounters>,
-    pub mine_count: u16,
+    pub grid: grid::Grid<Tile, u16, MinefieldCounters>,
+    pub mine_count: u32,
     sol: PartialSolution,
+    loss: Option<LossExplanation>,
+    /// Whether every move is checked against the brute force.
+    verify: bool,
 }
 
 impl Minefield {
-    pub fn create_random(width: u8, height: u8, mine_count: u16, rng: &mut impl rand::Rng) -> Minefield {
-
+    pub fn create_random(width: u16, height: u16, mine_count: u32, topology: Arc<dyn Topology>,
+        backend: Arc<dyn Backend>, rng: &mut impl rand::Rng) -> Result<Minefield, GameError>
+    {
         let swidth = usize::from(width);
         let total_size = swidth * usize::from(height);
-        let smine_count = usize::from(mine_count);
+        let smine_count = mine_count as usize;
 
         if smine_count > total_size {
-            panic!("More mines than it fits in the field!");
+            return Err(GameError::TooManyMines { mine_count, cell_count: total_size });
         }
 
         let mut flattened = vec![
@@ -75,46 +144,130 @@ impl Minefield {
 
         seq::SliceRandom::shuffle(&mut flattened[..], rng);
 
-        let sol = PartialSolution::new(width, height, mine_count);
+        let sol = PartialSolution::new(width, height, mine_count, topology, backend);
         //sol.print();
 
-        Minefield {
-            grid: grid::Grid::from_vec(width, height, Default::default(), flattened).unwrap(),
-            mine_count, sol
+        Ok(Minefield {
+            grid: grid::Grid::from_vec(width, height, Default::default(), flattened)
+                .expect("There must be a tile for each cell"),
+            mine_count, sol, loss: None, verify: false
+        })
+    }
+
+    /// Creates a field as in the position, with the mines laid out at random
+    /// among the layouts consistent with the clues.
+    pub fn from_position(position: &Position, backend: Arc<dyn Backend>,
+        rng: &mut impl rand::Rng) -> Result<Minefield, GameError>
+    {
+        let cells = &position.cells;
+        let mut sol = PartialSolution::from_position(position, backend)?;
+
+        let mut grid = grid::Grid::new(cells.width(), cells.height(), MinefieldCounters::default(),
+            Tile::Hidden(Content::Empty, UserMarking::None));
+        for row in 0..cells.height() {
+            for col in 0..cells.width() {
+                grid.set(row, col, match *cells.get(row, col) {
+                    Cell::Hidden(mark) => Tile::Hidden(if sol.is_known_mine(row, col) {
+                            Content::Mine
+                        } else {
+                            Content::Empty
+                        },
+                    mark),
+                    Cell::Revealed(clue) => Tile::Revealed(clue)
+                })?;
+            }
         }
+
+        // The mines the solver has already settled are never laid out again:
+        let is_consistent = sol.find_acomodating_solution(rng, std::iter::empty(),
+            |row, col, is_mine| Self::reconfigure_tile(&mut grid, row, col, is_mine))?;
+        if !is_consistent {
+            return Err(SolverError::MineCount.into());
+        }
+
+        Ok(Minefield {
+            grid, mine_count: position.mine_count, sol, loss: None, verify: false
+        })
     }
 
-    pub fn reveal(&mut self, rng: &mut impl rand::Rng, row: u8, col: u8) -> bool
+    /// The board as the player sees it.
+    pub fn position(&self) -> Position
+    {
+        let cells = self.grid.rows().flatten().map(|tile| match *tile {
+            Tile::Hidden(_, mark) => Cell::Hidden(mark),
+            Tile::Revealed(clue) => Cell::Revealed(clue)
+        }).collect();
+
+        Position {
+            mine_count: self.mine_count,
+            topology: self.sol.shared_topology(),
+            cells: grid::Grid::from_vec(self.width(), self.height(), (), cells)
+                .expect("There must be a cell for each tile"),
+        }
+    }
+
+    /// Reveals the tile, or the neighbors of a clue with all its mines
+    /// flagged, and returns whether the player survived.
+    pub fn reveal(&mut self, rng: &mut impl rand::Rng, row: u16, col: u16) -> Result<bool, GameError>
     {
         let cells = self.find_revealed_cells(row, col, true);
-        let was_something_revealed = cells.len() > 0;
+        let was_something_revealed = !cells.is_empty();
+
+        // Before anything changes, find out by brute force if the move
+        // should be survivable:
+        let expected = if self.verify {
+            let revealed: Vec<_> = cells.iter()
+                .map(|&(row, col, _)| (row, col)).collect();
+            verify::Layouts::enumerate(self, &revealed)
+        } else {
+            None
+        };
 
         let survived = {
             let had_mine = cells.iter().any(|&(_,_,mine)| mine);
 
             !had_mine || self.try_reacomodate(rng, cells.iter()
-                .map(|&(row, col, _)| (row, col)))
+                .map(|&(row, col, _)| (row, col)))?
         };
 
+        if let Some(expected) = expected {
+            if survived != (expected.count > 0) {
+                return Err(self.mismatch(format!(
+                    "revealing ({}, {}) {} fatal, but the solver says it {}",
+                    row, col,
+                    if expected.count > 0 { "is not" } else { "is" },
+                    if survived { "is not" } else { "is" })));
+            }
+        }
+
+        // Find out why before revealing anything else, while the
+        // solver still knows only what the player knew:
+        if !survived {
+            let revealed: Vec<_> = cells.iter()
+                .map(|&(row, col, _)| (row, col)).collect();
+            self.loss = self.sol.explain_loss(&revealed);
+        }
+
         // Independently of surviving, reveal what is revealable:
         for (row, col, _) in cells {
-            self.recursive_reveal(row, col);
+            self.flood_reveal(row, col)?;
         }
 
         // Update the solver only if something changed:
         if survived && was_something_revealed {
-            let begin = std::time::Instant::now();
-            self.sol.find_graph_solutions();
-            let delta = std::time::Instant::now() - begin;
-            println!("Search time: {:0.09}", delta.as_secs_f64());
+            timing::measure("Search time", || self.sol.find_graph_solutions())?;
 
             //self.sol.print();
+
+            if self.verify {
+                self.cross_check()?;
+            }
         }
 
-        survived
+        Ok(survived)
     }
 
-    pub fn switch_mark(&mut self, row: u8, col: u8)
+    pub fn switch_mark(&mut self, row: u16, col: u16) -> Result<(), GameError>
     {
         if let Tile::Hidden(c, mark) = *self.grid.get(row, col) {
             self.grid.set(row, col, Tile::Hidden(c, match mark {
@@ -125,17 +278,156 @@ impl Minefield {
                     UserMarking::QuestionMark
                 },
                 UserMarking::QuestionMark => UserMarking::None
-            }));
+            }))?;
         }
+
+        Ok(())
+    }
+
+    /// Turns on or off checking every move against the brute force of the
+    /// verify module, which is very slow unless the board is small.
+    pub fn set_verification(&mut self, enabled: bool)
+    {
+        self.verify = enabled;
+    }
+
+    /// Reports the progress of the solver from now on to "progress".
+    pub fn track_progress(&mut self, progress: Arc<Progress>)
+    {
+        self.sol.track_progress(progress);
+    }
+
+    /// A hidden cell the player can prove to be a mine or empty, preferring
+    /// the empty ones, and leaving out the mines already flagged.
+    pub fn hint(&mut self) -> Option<Hint>
+    {
+        let grid = &self.grid;
+        self.sol.find_hint(|(row, col), is_mine| {
+            !is_mine || !matches!(grid.get(row, col), Tile::Hidden(_, UserMarking::Flag))
+        })
+    }
+
+    /// Probability of each tile holding a mine, given what the player knows.
+    pub fn mine_probabilities(&mut self) -> grid::Grid<f64, u16, ()>
+    {
+        self.sol.mine_probabilities()
+    }
+
+    /// Why the player lost, if the last move was fatal.
+    pub fn loss_explanation(&self) -> Option<&LossExplanation>
+    {
+        self.loss.as_ref()
     }
 
     pub fn is_all_revealed(&self) -> bool
     {
-        self.grid.counters.revealed_count + self.mine_count == self.width() as u16 * self.height() as u16
+        self.grid.counters.revealed_count + self.mine_count == self.width() as u32 * self.height() as u32
     }
 
-    fn find_revealed_cells(&self, row: u8, col: u8, process_revealed: bool)
-        -> Vec<(u8, u8, bool)>
+    /// Checks what the game relies on, in the field and in the solver,
+    /// for testing: the counters match the tiles, the clues match the mines
+    /// around them, and the solver agrees with the real layout.
+    pub fn check_invariants(&self) -> Result<(), GameError>
+    {
+        let mut counters = MinefieldCounters::default();
+        let mut mine_count = 0u32;
+        for (row, tiles) in (0u16..).zip(self.grid.rows()) {
+            for (col, tile) in (0u16..).zip(tiles.iter()) {
+                match *tile {
+                    Tile::Hidden(content, mark) => {
+                        mine_count += matches!(content, Content::Mine) as u32;
+                        counters.flag_count += matches!(mark, UserMarking::Flag) as u32;
+                    },
+                    Tile::Revealed(clue) => {
+                        counters.revealed_count += 1;
+                        if clue != self.count_neighbor_bombs(row, col) {
+                            return Err(GameError::Invariant("a clue doesn't match its neighbors"));
+                        }
+                    }
+                }
+            }
+        }
+
+        if counters.flag_count != self.grid.counters.flag_count
+            || counters.revealed_count != self.grid.counters.revealed_count
+        {
+            return Err(GameError::Invariant("the counters don't match the tiles"));
+        }
+        if mine_count != self.mine_count {
+            return Err(GameError::Invariant("the mines don't add up to the total"));
+        }
+
+        self.sol.check_invariants(|row, col| {
+            matches!(self.grid.get(row, col), Tile::Hidden(Content::Mine, _))
+        })?;
+        Ok(())
+    }
+
+    /// Compares what the solver knows about the board to the brute force of
+    /// all the layouts, and reports the first difference found.
+    fn cross_check(&mut self) -> Result<(), GameError>
+    {
+        let layouts = match verify::Layouts::enumerate(self, &[]) {
+            Some(layouts) => layouts,
+            // Too big to check.
+            None => return Ok(())
+        };
+
+        let is_feasible = self.sol.is_feasible();
+        if is_feasible != (layouts.count > 0) {
+            return Err(self.mismatch(format!(
+                "there are {} layouts, but the solver says the board is {}",
+                layouts.count, if is_feasible { "feasible" } else { "infeasible" })));
+        }
+
+        let expected = layouts.forced();
+        let found: BTreeMap<_, _> = self.sol.find_forced_cells().into_iter().collect();
+        let describe = |forced: Option<&bool>| match forced {
+            Some(true) => "a mine",
+            Some(false) => "empty",
+            None => "not forced",
+        };
+        for key in expected.keys().chain(found.keys()) {
+            if expected.get(key) != found.get(key) {
+                return Err(self.mismatch(format!(
+                    "tile {:?} is {}, but the solver says it is {}",
+                    key, describe(expected.get(key)), describe(found.get(key)))));
+            }
+        }
+
+        if layouts.count == 0 {
+            return Ok(());
+        }
+
+        // The solver counts in floating point, so only up to rounding:
+        const TOLERANCE: f64 = 1e-9;
+        let ln_layouts = self.sol.ln_layouts();
+        if (ln_layouts - (layouts.count as f64).ln()).abs() > TOLERANCE {
+            return Err(self.mismatch(format!(
+                "there are {} layouts, but the solver counts {}",
+                layouts.count, ln_layouts.exp())));
+        }
+
+        let probabilities = self.sol.mine_probabilities();
+        for (&(row, col), &mines) in layouts.mines.iter() {
+            let probability = *probabilities.get(row, col);
+            if (probability - mines as f64 / layouts.count as f64).abs() > TOLERANCE {
+                return Err(self.mismatch(format!(
+                    "tile {:?} is a mine in {} of the {} layouts, but the solver gives it \
+                    a probability of {}", (row, col), mines, layouts.count, probability)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn mismatch(&self, report: String) -> GameError
+    {
+        GameError::Mismatch(format!("{}\n{}", report, verify::dump(self)))
+    }
+
+    fn find_revealed_cells(&self, row: u16, col: u16, process_revealed: bool)
+        -> Vec<(u16, u16, bool)>
     {
         match self.grid.get(row, col) {
             Tile::Hidden(_, UserMarking::Flag) => Vec::new(),
@@ -163,54 +455,58 @@ impl Minefield {
         }
     }
 
-    fn try_reacomodate(&mut self, rng: &mut impl rand::Rng, revealed: impl IntoIterator<Item = (u8, u8)>)
-        -> bool
+    fn try_reacomodate(&mut self, rng: &mut impl rand::Rng, revealed: impl IntoIterator<Item = (u16, u16)>)
+        -> Result<bool, GameError>
     {
-        let begin = Instant::now();
-
         let grid = &mut self.grid;
+        let sol = &mut self.sol;
 
-        let ret = self.sol.find_acomodating_solution(rng, revealed, |row, col, is_mine| {
-            match *grid.get(row, col) {
-                Tile::Hidden(_, m) => {
-                    grid.set(row, col, Tile::Hidden(if is_mine {
-                            Content::Mine
-                        } else {
-                            Content::Empty
-                        },
-                    m));
-                },
-                _ => panic!("Can not reaccommodate revealed tiles")
-            };
-        });
-
-        let elapsed = Instant::now() - begin;
-        println!("Reconfiguration time: {:0.06}", elapsed.as_secs_f64());
+        timing::measure("Reconfiguration time", || sol.find_acomodating_solution(rng, revealed,
+            |row, col, is_mine| Self::reconfigure_tile(grid, row, col, is_mine)))
+    }
 
-        ret
+    fn reconfigure_tile(grid: &mut grid::Grid<Tile, u16, MinefieldCounters>, row: u16, col: u16,
+        is_mine: bool) -> Result<(), GameError>
+    {
+        match *grid.get(row, col) {
+            Tile::Hidden(_, m) => {
+                grid.set(row, col, Tile::Hidden(if is_mine {
+                        Content::Mine
+                    } else {
+                        Content::Empty
+                    },
+                m))
+            },
+            // Can not reaccommodate revealed tiles:
+            _ => Err(SolverError::AlreadyRevealed((row, col)).into())
+        }
     }
 
-    fn recursive_reveal(&mut self, row: u8, col: u8)
+    /// Reveals the tile, and the tiles around it as long as they have no
+    /// mine around, in the order a recursion would, but with a stack of its
+    /// own, as the regions without mines can be too large for the call stack.
+    fn flood_reveal(&mut self, row: u16, col: u16) -> Result<(), GameError>
     {
-        match *self.grid.get(row, col) {
-            Tile::Hidden(Content::Empty, _) => {
+        let mut stack = vec![(row, col)];
+        while let Some((row, col)) = stack.pop() {
+            if let Tile::Hidden(Content::Empty, _) = *self.grid.get(row, col) {
                 let bomb_count = self.count_neighbor_bombs(row, col);
 
-                self.grid.set(row, col, Tile::Revealed(bomb_count));
-                self.sol.add_clue((row, col), bomb_count);
+                self.grid.set(row, col, Tile::Revealed(bomb_count))?;
+                self.sol.add_clue((row, col), bomb_count)?;
 
                 if bomb_count == 0 {
-                    for (row, col) in self.neighbors_of(row, col) {
-                        self.recursive_reveal(row, col);
-                    }
-                };
-            },
-            // TODO: reveal mine to display to the player the reason of losing
-            _ => ()
+                    // Reversed, so that the first neighbor is revealed first:
+                    let neighbors: Vec<_> = self.neighbors_of(row, col).collect();
+                    stack.extend(neighbors.into_iter().rev());
+                }
+            }
         }
+
+        Ok(())
     }
 
-    fn count_neighbor_bombs(&self, row: u8, col: u8) -> u8
+    fn count_neighbor_bombs(&self, row: u16, col: u16) -> u8
     {
         self.neighbors_of(row, col).fold(0, |accum, (row, col)| {
             accum + match self.grid.get(row, col) {
@@ -222,13 +518,116 @@ impl Minefield {
 }
 
 impl NeighborIterable for Minefield {
-    fn width(&self) -> u8
+    fn width(&self) -> u16
     {
         self.grid.width()
     }
 
-    fn height(&self) -> u8
+    fn height(&self) -> u16
     {
         self.grid.height()
     }
+
+    fn topology(&self) -> &dyn Topology
+    {
+        self.sol.topology()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::backend;
+    use crate::topology;
+    use proptest::prelude::*;
+    use rand_core::SeedableRng;
+
+    #[test]
+    fn reveals_boards_larger_than_255()
+    {
+        // A single region without mines, revealed all at once by the first
+        // move, far too large to be revealed recursively:
+        let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(0);
+        let mut minefield = Minefield::create_random(400, 300, 0,
+            Arc::new(topology::Square), Arc::new(backend::Diagram), &mut rng).unwrap();
+        assert!(minefield.reveal(&mut rng, 299, 399).unwrap());
+        assert!(minefield.is_all_revealed());
+        assert_eq!(minefield.grid.counters.revealed_count, 400 * 300);
+        minefield.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn hints_only_mines_not_flagged()
+    {
+        let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(0);
+        let mut hint = |board: &str| {
+            let position = board.parse().unwrap();
+            Minefield::from_position(&position, Arc::new(backend::Diagram), &mut rng).unwrap()
+                .hint().map(|hint| (hint.cell, hint.is_mine))
+        };
+
+        assert_eq!(hint("mines: 1\n.\n1\n"), Some(((0, 0), true)));
+        assert_eq!(hint("mines: 1\nF\n1\n"), None);
+        // A safe cell is worth a hint, even if flagged:
+        assert_eq!(hint("mines: 2\nF.F\n121\n"), Some(((0, 1), false)));
+    }
+
+    /// Solves the board again from scratch, and checks the solver of the
+    /// game, updated after each move, knows the same.
+    fn check_incremental(minefield: &mut Minefield, backend: Arc<dyn Backend>)
+        -> Result<(), TestCaseError>
+    {
+        let mut fresh = PartialSolution::from_position(&minefield.position(), backend).unwrap();
+        let sol = &mut minefield.sol;
+
+        let mut forced = sol.find_forced_cells();
+        let mut fresh_forced = fresh.find_forced_cells();
+        forced.sort_unstable();
+        fresh_forced.sort_unstable();
+        prop_assert_eq!(forced, fresh_forced);
+
+        let (ln_layouts, fresh_ln_layouts) = (sol.ln_layouts(), fresh.ln_layouts());
+        prop_assert!((ln_layouts - fresh_ln_layouts).abs() < 1e-9,
+            "{} layouts instead of {}", ln_layouts.exp(), fresh_ln_layouts.exp());
+
+        let probabilities = sol.mine_probabilities();
+        let fresh_probabilities = fresh.mine_probabilities();
+        for (row, (probs, fresh_probs)) in probabilities.rows().zip(fresh_probabilities.rows()).enumerate() {
+            for (col, (prob, fresh_prob)) in probs.iter().zip(fresh_probs).enumerate() {
+                prop_assert!((prob - fresh_prob).abs() < 1e-9,
+                    "({}, {}) has probability {} instead of {}", row, col, prob, fresh_prob);
+            }
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn solves_incrementally_as_from_scratch(
+            (topology, backend, width, height, mine_count, seed, clicks) in
+                (0..topology::all().len(), 0..3usize, 2u16..=6, 2u16..=6)
+                    .prop_flat_map(|(topology, backend, width, height)| (
+                        Just(topology), Just(backend), Just(width), Just(height),
+                        0..=width as u32 * height as u32,
+                        any::<u64>(),
+                        prop::collection::vec((0..height, 0..width), 1..40)
+                    ))
+        ) {
+            let backend = backend::by_name(["diagram", "enumerator", "sat"][backend]).unwrap();
+            let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(seed);
+            let mut minefield = Minefield::create_random(width, height, mine_count,
+                topology::all()[topology].clone(), backend.clone(), &mut rng).unwrap();
+
+            for (row, col) in clicks {
+                if !minefield.reveal(&mut rng, row, col).unwrap() {
+                    break;
+                }
+                check_incremental(&mut minefield, backend.clone())?;
+                if minefield.is_all_revealed() {
+                    break;
+                }
+            }
+        }
+    }
 }
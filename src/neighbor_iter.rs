@@ -1,64 +1,77 @@
+use super::topology::{self, Topology};
+use smallvec::SmallVec;
+
+/// How many neighbors are kept without allocating: the 24 of the 5x5 block
+/// of Neighborhood::RADIUS_2, the most of any topology by_name() finds.
+/// Larger neighborhoods go to the heap.
+pub const INLINE_NEIGHBORS: usize = 24;
+
+/// The neighbors of a tile, as collected.
+pub type Neighbors<T> = SmallVec<[T; INLINE_NEIGHBORS]>;
+
 pub struct NeighborIter
 {
-    width: u8,
-    height: u8,
-    row: u8,
-    col: u8,
-    i: u8
+    neighbors: smallvec::IntoIter<[(u16, u16); INLINE_NEIGHBORS]>
 }
 
 impl NeighborIter
 {
-    const DELTAS: [(i16, i16); 8] = [
-        (-1, -1), (-1, 0), (-1, 1),
-        ( 0, -1),          ( 0, 1),
-        ( 1, -1), ( 1, 0), ( 1, 1)
-    ];
-
-    fn new(width: u8, height: u8, row: u8, col: u8) -> Self
+    /// The tiles at the given offsets from the tile at "row" and "col",
+    /// leaving out the ones off the board, or, if "wrap", bringing them back
+    /// from the opposite side.
+    pub fn from_deltas(width: u16, height: u16, row: u16, col: u16,
+        deltas: &[(i16, i16)], wrap: bool) -> Self
     {
-        Self{width, height, row, col, i: 0}
+        let mut neighbors = Neighbors::new();
+        for (dr, dc) in deltas {
+            let mut r = i32::from(*dr) + i32::from(row);
+            let mut c = i32::from(*dc) + i32::from(col);
+            if wrap {
+                r = r.rem_euclid(i32::from(height));
+                c = c.rem_euclid(i32::from(width));
+            } else if r < 0 || r >= i32::from(height) || c < 0 || c >= i32::from(width) {
+                continue;
+            }
+
+            // Wrapping around a small board can reach the same tile twice,
+            // or the tile itself:
+            let key = (r as u16, c as u16);
+            if key != (row, col) && !neighbors.contains(&key) {
+                neighbors.push(key);
+            }
+        }
+        Self{neighbors: neighbors.into_iter()}
     }
 }
 
 impl Iterator for NeighborIter
 {
-    type Item = (u8, u8);
+    type Item = (u16, u16);
 
     fn next(&mut self) -> Option<Self::Item>
     {
-        while self.i < 8 {
-            let (dr, dc) = Self::DELTAS[self.i as usize];
-            self.i += 1;
-
-            let row = dr + i16::from(self.row);
-            if row < 0 || row >= i16::from(self.height) {
-                continue;
-            }
-
-            let col = dc + i16::from(self.col);
-            if col < 0 || col >= i16::from(self.width) {
-                continue;
-            }
-
-            return Some((row as u8, col as u8));
-        }
-        None
+        self.neighbors.next()
     }
 
     fn size_hint(&self) -> (usize, Option<usize>)
     {
-        (3, Some(8))
+        self.neighbors.size_hint()
     }
 }
 
 pub trait NeighborIterable
 {
-    fn width(&self) -> u8;
-    fn height(&self) -> u8;
+    fn width(&self) -> u16;
+    fn height(&self) -> u16;
+
+    /// Which tiles touch each other, the square grid unless overridden.
+    fn topology(&self) -> &dyn Topology
+    {
+        &topology::Square
+    }
 
-    fn neighbors_of(&self, row: u8, col: u8) -> NeighborIter
+    fn neighbors_of(&self, row: u16, col: u16) -> NeighborIter
     {
-        NeighborIter::new(self.width(), self.height(), row, col)
+        self.topology().neighbors_of(self.width(), self.height(), row, col)
     }
 }
@@ -0,0 +1,266 @@
+//! A plain-text notation for a board in progress, as the player sees it.
+//!
+//! The first line gives the total number of mines, and each of the others a
+//! row of the board, where a digit is a revealed clue, "." a hidden tile, "F"
+//! a flagged one and "?" one marked with a question mark. Clues above 9, on
+//! the topologies where tiles have more neighbors, are the lowercase letters
+//! from "a", and clues above 35 are written in decimal between parentheses,
+//! like "(48)". The board is square unless a line "topology: <name>" follows the
+//! number of mines. Blank lines and lines starting with "#" are ignored, so
+//! that notes can go along:
+//!
+//! ```text
+//! # The 1-2-1 pattern.
+//! mines: 2
+//! ...
+//! 121
+//! ```
+//!
+//! Where the mines are is not part of the notation: when a position is
+//! loaded, they are laid out at random, consistently with the clues.
+
+use super::grid;
+use super::minefield::UserMarking;
+use super::topology::{self, Topology};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[derive(Copy, Clone)]
+pub enum Cell {
+    Hidden(UserMarking),
+    Revealed(u8),
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    /// The line where the error is, starting from 1, or 0 if the error is
+    /// about the whole board.
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A board in progress, as the player sees it.
+#[derive(Clone)]
+pub struct Position {
+    pub mine_count: u32,
+    pub topology: Arc<dyn Topology>,
+    pub cells: grid::Grid<Cell, u16, ()>,
+}
+
+impl Position {
+    /// The positions of the revealed tiles, with their clues.
+    pub fn clues(&self) -> impl Iterator<Item = ((u16, u16), u8)> + '_ {
+        (0u16..).zip(self.cells.rows()).flat_map(|(row, cells)| {
+            (0u16..)
+                .zip(cells.iter())
+                .filter_map(move |(col, cell)| match cell {
+                    Cell::Revealed(clue) => Some(((row, col), *clue)),
+                    Cell::Hidden(_) => None,
+                })
+        })
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "mines: {}", self.mine_count)?;
+        if self.topology.name() != "square" {
+            writeln!(f, "topology: {}", self.topology.name())?;
+        }
+        for cells in self.cells.rows() {
+            for cell in cells {
+                match cell {
+                    Cell::Hidden(UserMarking::None) => write!(f, ".")?,
+                    Cell::Hidden(UserMarking::Flag) => write!(f, "F")?,
+                    Cell::Hidden(UserMarking::QuestionMark) => write!(f, "?")?,
+                    Cell::Revealed(clue) => match char::from_digit(u32::from(*clue), 36) {
+                        Some(digit) => write!(f, "{}", digit)?,
+                        None => write!(f, "({})", clue)?,
+                    },
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Position {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, ParseError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .peekable();
+
+        let (line, mines) = lines.next().ok_or(ParseError {
+            line: 1,
+            reason: "the board is empty",
+        })?;
+        let mine_count = mines
+            .strip_prefix("mines:")
+            .and_then(|mines| mines.trim().parse().ok())
+            .ok_or(ParseError {
+                line,
+                reason: "expected the number of mines, as \"mines: <count>\"",
+            })?;
+
+        let mut topology: Arc<dyn Topology> = Arc::new(topology::Square);
+        if let Some((line, name)) = lines.next_if(|(_, line)| line.starts_with("topology:")) {
+            topology = topology::by_name(name["topology:".len()..].trim()).ok_or(ParseError {
+                line,
+                reason: "unknown topology",
+            })?;
+        }
+
+        let mut width = None;
+        let mut data = Vec::new();
+        for (line, row) in lines {
+            let error = |reason| ParseError { line, reason };
+            let mut len = 0;
+            let mut chars = row.chars();
+            while let Some(c) = chars.next() {
+                data.push(match c {
+                    '.' => Cell::Hidden(UserMarking::None),
+                    'F' => Cell::Hidden(UserMarking::Flag),
+                    '?' => Cell::Hidden(UserMarking::QuestionMark),
+                    '0'..='9' | 'a'..='z' => {
+                        Cell::Revealed(c.to_digit(36).expect("A digit in base 36") as u8)
+                    }
+                    '(' => {
+                        let rest = chars.as_str();
+                        let clue = rest.find(')').and_then(|end| {
+                            chars = rest[end + 1..].chars();
+                            rest[..end].parse().ok()
+                        });
+                        Cell::Revealed(clue.ok_or_else(|| {
+                            error("expected a clue in decimal between parentheses")
+                        })?)
+                    }
+                    _ => return Err(error("expected a clue, \".\", \"F\" or \"?\"")),
+                });
+                len += 1;
+            }
+            if *width.get_or_insert(len) != len {
+                return Err(error("all the rows must be as long"));
+            }
+        }
+
+        let too_big = ParseError {
+            line: 0,
+            reason: "the board can have at most 65535 rows and 65535 columns",
+        };
+        let width = u16::try_from(width.unwrap_or(0)).map_err(|_| too_big.clone())?;
+        let height =
+            u16::try_from(data.len() / std::cmp::max(width as usize, 1)).map_err(|_| too_big)?;
+        if width == 0 {
+            return Err(ParseError {
+                line: 0,
+                reason: "the board has no tiles",
+            });
+        }
+
+        Ok(Self {
+            mine_count,
+            topology,
+            cells: grid::Grid::from_vec(width, height, (), data)
+                .expect("Every row must be as long"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend;
+    use crate::minefield::{GameError, Minefield};
+    use crate::solver::PartialSolution;
+    use rand_core::SeedableRng;
+    use std::sync::Arc;
+
+    const BOARD: &str = "\
+mines: 4
+F1...
+110..
+000?.
+";
+
+    #[test]
+    fn loads_and_prints_the_same() {
+        let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(0);
+        let position: Position = BOARD.parse().unwrap();
+        let minefield =
+            Minefield::from_position(&position, Arc::new(backend::Diagram), &mut rng).unwrap();
+        minefield.check_invariants().unwrap();
+        assert_eq!(minefield.position().to_string(), BOARD);
+
+        // The solver doesn't know about the marks:
+        let sol = PartialSolution::from_position(&position, Arc::new(backend::Diagram)).unwrap();
+        assert_eq!(sol.position().to_string(), BOARD.replace(['F', '?'], "."));
+    }
+
+    #[test]
+    fn rejects_impossible_boards() {
+        let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(0);
+        for board in ["mines: 1\n1.\n.3\n", "mines: 0\n.1\n"] {
+            let position: Position = board.parse().unwrap();
+            assert!(matches!(
+                Minefield::from_position(&position, Arc::new(backend::Diagram), &mut rng),
+                Err(GameError::Solver(_))
+            ));
+        }
+
+        for board in ["", "mines 1\n..", "mines: 1\n..\n...", "mines: 1\n.A"] {
+            assert!(board.parse::<Position>().is_err());
+        }
+    }
+
+    #[test]
+    fn keeps_the_topology() {
+        let board = "mines: 3\ntopology: triangular\n.....\n..c..\n";
+        let position: Position = board.parse().unwrap();
+        assert_eq!(position.topology.name(), "triangular");
+        assert_eq!(position.clues().collect::<Vec<_>>(), [((1, 2), 12)]);
+        assert_eq!(position.to_string(), board);
+
+        assert!("mines: 1\ntopology: cubic\n..".parse::<Position>().is_err());
+    }
+
+    #[test]
+    fn writes_clues_past_z() {
+        let board = "mines: 1\nz(36)\n(48).\n";
+        let position: Position = board.parse().unwrap();
+        assert_eq!(
+            position.clues().collect::<Vec<_>>(),
+            [((0, 0), 35), ((0, 1), 36), ((1, 0), 48)]
+        );
+        assert_eq!(position.to_string(), board);
+
+        for board in ["mines: 1\n(36\n", "mines: 1\n(x)\n", "mines: 1\n(256)\n"] {
+            assert!(board.parse::<Position>().is_err());
+        }
+    }
+
+    #[test]
+    fn reads_boards_larger_than_255() {
+        let board = format!("mines: 1\n{}\n{}1\n", ".".repeat(300), "0".repeat(299));
+        let position: Position = board.parse().unwrap();
+        assert_eq!(position.clues().last(), Some(((1, 299), 1)));
+        assert_eq!(position.to_string(), board);
+
+        let too_wide = format!("mines: 1\n{}\n", ".".repeat(70000));
+        assert!(too_wide.parse::<Position>().is_err());
+    }
+}
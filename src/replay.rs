@@ -0,0 +1,410 @@
+//! Recording of the moves of a game, to play it again exactly as it was.
+//!
+//! Everything random in a game comes from a generator seeded once, so the
+//! seed and the moves, in order, are enough to reproduce every mine swapped
+//! by the solver. After each move, the recording also keeps a digest of the
+//! board, so that a replay going differently is noticed at once.
+
+use super::backend;
+use super::minefield::{GameError, Minefield};
+use super::topology;
+use super::verify;
+use hex::FromHex;
+use rand_core::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+
+/// First line of every recording, with the version of the format.
+const HEADER: &str = "mineswapper replay 2";
+
+/// First line of the recordings made before boards had topologies, which
+/// are all of square boards.
+const HEADER_V1: &str = "mineswapper replay 1";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    Reveal(u16, u16),
+    Mark(u16, u16),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Step {
+    /// When the move was made, since the game was created.
+    pub time: Duration,
+    pub action: Action,
+    /// Digest of the board after the move.
+    digest: u64,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    /// The recording is malformed at the given line.
+    Parse {
+        line: usize,
+        reason: &'static str,
+    },
+    UnknownBackend(String),
+    UnknownTopology(String),
+    Game(GameError),
+    /// The board after the given step is not the one recorded.
+    Diverged {
+        step: usize,
+    },
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(err: io::Error) -> Self {
+        ReplayError::Io(err)
+    }
+}
+
+impl From<GameError> for ReplayError {
+    fn from(err: GameError) -> Self {
+        ReplayError::Game(err)
+    }
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "{}", err),
+            ReplayError::Parse { line, reason } => write!(f, "line {}: {}", line, reason),
+            ReplayError::UnknownBackend(name) => write!(f, "unknown solver backend \"{}\"", name),
+            ReplayError::UnknownTopology(name) => write!(f, "unknown topology \"{}\"", name),
+            ReplayError::Game(err) => write!(f, "{}", err),
+            ReplayError::Diverged { step } => {
+                write!(
+                    f,
+                    "the board differs from the recording after move {}",
+                    step + 1
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReplayError::Io(err) => Some(err),
+            ReplayError::Game(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// What is needed to play a game again: how it was created, and the moves
+/// that changed the board.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub seed: [u8; 32],
+    pub width: u16,
+    pub height: u16,
+    pub mine_count: u32,
+    /// Name of the topology of the board, as given to topology::by_name().
+    pub topology: String,
+    /// Name of the solver backend, as given to backend::by_name().
+    pub backend: String,
+    pub steps: Vec<Step>,
+}
+
+impl Recording {
+    pub fn new(
+        seed: [u8; 32],
+        width: u16,
+        height: u16,
+        mine_count: u32,
+        topology: &str,
+        backend: &str,
+    ) -> Self {
+        Self {
+            seed,
+            width,
+            height,
+            mine_count,
+            topology: topology.to_owned(),
+            backend: backend.to_owned(),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Creates the game as it was at the start of the recording, with the
+    /// random generator the moves must be made with.
+    pub fn start(&self) -> Result<(Minefield, rand_xoshiro::Xoshiro256StarStar), ReplayError> {
+        let backend = backend::by_name(&self.backend)
+            .ok_or_else(|| ReplayError::UnknownBackend(self.backend.clone()))?;
+        let topology = topology::by_name(&self.topology)
+            .ok_or_else(|| ReplayError::UnknownTopology(self.topology.clone()))?;
+        let mut rng = rand_xoshiro::Xoshiro256StarStar::from_seed(self.seed);
+        let minefield = Minefield::create_random(
+            self.width,
+            self.height,
+            self.mine_count,
+            topology,
+            backend,
+            &mut rng,
+        )?;
+        Ok((minefield, rng))
+    }
+
+    /// Adds a move, made at "time", that left the board as in "minefield".
+    pub fn record(&mut self, time: Duration, action: Action, minefield: &Minefield) {
+        self.steps.push(Step {
+            time,
+            action,
+            digest: digest(minefield),
+        });
+    }
+
+    /// Checks that the board after the step "step" is as recorded.
+    pub fn check(&self, step: usize, minefield: &Minefield) -> Result<(), ReplayError> {
+        if self.steps[step].digest != digest(minefield) {
+            return Err(ReplayError::Diverged { step });
+        }
+        Ok(())
+    }
+
+    /// Plays the whole game again, checking the board after every move, and
+    /// returns how it ended.
+    pub fn replay(&self) -> Result<Minefield, ReplayError> {
+        let (mut minefield, mut rng) = self.start()?;
+        for (i, step) in self.steps.iter().enumerate() {
+            match step.action {
+                Action::Reveal(row, col) => {
+                    minefield.reveal(&mut rng, row, col)?;
+                }
+                Action::Mark(row, col) => minefield.switch_mark(row, col)?,
+            }
+            self.check(i, &minefield)?;
+        }
+        Ok(minefield)
+    }
+
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{}", HEADER)?;
+        writeln!(out, "seed {}", hex::encode(self.seed))?;
+        writeln!(
+            out,
+            "board {} {} {}",
+            self.width, self.height, self.mine_count
+        )?;
+        writeln!(out, "backend {}", self.backend)?;
+        writeln!(out, "topology {}", self.topology)?;
+        for step in self.steps.iter() {
+            let (name, row, col) = match step.action {
+                Action::Reveal(row, col) => ("reveal", row, col),
+                Action::Mark(row, col) => ("mark", row, col),
+            };
+            writeln!(
+                out,
+                "{} {} {} {} {:016x}",
+                step.time.as_millis(),
+                name,
+                row,
+                col,
+                step.digest
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(input: impl BufRead) -> Result<Self, ReplayError> {
+        let mut lines = input.lines().enumerate();
+        let mut next_line = |reason| -> Result<(usize, String), ReplayError> {
+            match lines.next() {
+                Some((i, line)) => Ok((i + 1, line?)),
+                None => Err(ReplayError::Parse { line: 0, reason }),
+            }
+        };
+
+        let (line, header) = next_line("the recording is empty")?;
+        if header != HEADER && header != HEADER_V1 {
+            return Err(ReplayError::Parse {
+                line,
+                reason: "not a recording, or of an unsupported version",
+            });
+        }
+
+        let (line, seed) = next_line("the seed is missing")?;
+        let seed = seed
+            .strip_prefix("seed ")
+            .and_then(|seed| <[u8; 32]>::from_hex(seed).ok())
+            .ok_or(ReplayError::Parse {
+                line,
+                reason: "expected the seed, as 64 hexadecimal digits",
+            })?;
+
+        let (line, board) = next_line("the board size is missing")?;
+        let bad_board = ReplayError::Parse {
+            line,
+            reason: "expected the width, height and number of mines",
+        };
+        let board: Vec<&str> = match board.strip_prefix("board ") {
+            Some(board) => board.split(' ').collect(),
+            None => return Err(bad_board),
+        };
+        let (width, height, mine_count) = match board[..] {
+            [width, height, mine_count] => {
+                match (width.parse(), height.parse(), mine_count.parse()) {
+                    (Ok(width), Ok(height), Ok(mine_count)) => (width, height, mine_count),
+                    _ => return Err(bad_board),
+                }
+            }
+            _ => return Err(bad_board),
+        };
+
+        let (line, backend) = next_line("the solver backend is missing")?;
+        let backend = backend.strip_prefix("backend ").ok_or(ReplayError::Parse {
+            line,
+            reason: "expected the name of the solver backend",
+        })?;
+
+        let topology = if header == HEADER_V1 {
+            "square".to_owned()
+        } else {
+            let (line, topology) = next_line("the topology is missing")?;
+            topology
+                .strip_prefix("topology ")
+                .ok_or(ReplayError::Parse {
+                    line,
+                    reason: "expected the name of the topology",
+                })?
+                .to_owned()
+        };
+
+        let mut recording = Self::new(seed, width, height, mine_count, &topology, backend);
+        for (i, step) in lines {
+            let step = step?;
+            recording
+                .steps
+                .push(parse_step(&step).ok_or(ReplayError::Parse {
+                    line: i + 1,
+                    reason: "expected the time, the move, the row, the column and the digest",
+                })?);
+        }
+
+        Ok(recording)
+    }
+}
+
+fn parse_step(step: &str) -> Option<Step> {
+    let fields: Vec<&str> = step.split(' ').collect();
+    let (time, name, row, col, digest) = match fields[..] {
+        [time, name, row, col, digest] => (time, name, row, col, digest),
+        _ => return None,
+    };
+
+    let (row, col) = (row.parse().ok()?, col.parse().ok()?);
+    Some(Step {
+        time: Duration::from_millis(time.parse().ok()?),
+        action: match name {
+            "reveal" => Action::Reveal(row, col),
+            "mark" => Action::Mark(row, col),
+            _ => return None,
+        },
+        digest: u64::from_str_radix(digest, 16).ok()?,
+    })
+}
+
+/// 64 bit FNV-1a hash of where the mines are and what was revealed, which,
+/// unlike the hashers of the standard library, is the same in every build.
+fn digest(minefield: &Minefield) -> u64 {
+    verify::dump(minefield)
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    /// Plays the game of the recording with random moves, recording them.
+    fn record_game(mut recording: Recording) -> (Recording, Minefield) {
+        let (mut minefield, mut rng) = recording.start().unwrap();
+
+        // The moves are chosen by another generator, as a player would:
+        let mut player = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(7);
+        for i in 0..40 {
+            let row = player.gen_range(0..recording.height);
+            let col = player.gen_range(0..recording.width);
+            let time = Duration::from_millis(i * 1000);
+            if player.gen_bool(0.2) {
+                minefield.switch_mark(row, col).unwrap();
+                recording.record(time, Action::Mark(row, col), &minefield);
+            } else {
+                let survived = minefield.reveal(&mut rng, row, col).unwrap();
+                recording.record(time, Action::Reveal(row, col), &minefield);
+                if !survived || minefield.is_all_revealed() {
+                    break;
+                }
+            }
+        }
+        (recording, minefield)
+    }
+
+    #[test]
+    fn replays_the_same_game() {
+        for backend in ["diagram", "enumerator", "sat"] {
+            let (recording, minefield) =
+                record_game(Recording::new([42; 32], 16, 16, 60, "square", backend));
+
+            let mut file = Vec::new();
+            recording.write_to(&mut file).unwrap();
+            let read = Recording::read_from(&file[..]).unwrap();
+            assert_eq!(read.steps.len(), recording.steps.len());
+
+            let replayed = read.replay().unwrap();
+            assert_eq!(verify::dump(&replayed), verify::dump(&minefield));
+        }
+    }
+
+    #[test]
+    fn notices_a_different_game() {
+        let (mut recording, _) =
+            record_game(Recording::new([42; 32], 16, 16, 60, "square", "diagram"));
+        recording.seed = [43; 32];
+        assert!(matches!(
+            recording.replay(),
+            Err(ReplayError::Diverged { step: 0 })
+        ));
+    }
+
+    #[test]
+    fn replays_every_topology() {
+        for topology in topology::all() {
+            let (recording, minefield) = record_game(Recording::new(
+                [42; 32],
+                9,
+                9,
+                10,
+                topology.name(),
+                "diagram",
+            ));
+            let mut file = Vec::new();
+            recording.write_to(&mut file).unwrap();
+            let replayed = Recording::read_from(&file[..]).unwrap().replay().unwrap();
+            assert_eq!(verify::dump(&replayed), verify::dump(&minefield));
+        }
+    }
+
+    #[test]
+    fn reads_recordings_of_square_boards_without_topology() {
+        let (recording, _) = record_game(Recording::new([42; 32], 16, 16, 60, "square", "diagram"));
+        let mut file = Vec::new();
+        recording.write_to(&mut file).unwrap();
+        let file = String::from_utf8(file)
+            .unwrap()
+            .replace(HEADER, HEADER_V1)
+            .replace("topology square\n", "");
+
+        let read = Recording::read_from(file.as_bytes()).unwrap();
+        assert_eq!(read.topology, "square");
+        read.replay().unwrap();
+    }
+}
@@ -57,19 +57,16 @@ where
             return Status::Captured;
         }
 
-        match event {
-            Event::Mouse(mouse::Event::ButtonReleased(Button::Right)) => {
-                if let Some(msg) = &self.on_right_click {
-                    let bounds = layout.bounds();
+        if let Event::Mouse(mouse::Event::ButtonReleased(Button::Right)) = event {
+            if let Some(msg) = &self.on_right_click {
+                let bounds = layout.bounds();
 
-                    if bounds.contains(cursor_position) {
-                        shell.publish((*msg).clone());
-                    }
-
-                    return Status::Captured;
+                if bounds.contains(cursor_position) {
+                    shell.publish((*msg).clone());
                 }
+
+                return Status::Captured;
             }
-            _ => {}
         }
 
         Status::Ignored
@@ -133,15 +130,15 @@ where
     }
 }
 
-impl<'a, T, Message: Clone, Renderer> Into<iced_native::Element<'a, Message, Renderer>>
-    for RightClickable<T, Message>
+impl<'a, T, Message: Clone, Renderer> From<RightClickable<T, Message>>
+    for iced_native::Element<'a, Message, Renderer>
 where
     Renderer: iced_native::Renderer,
     RightClickable<T, Message>: iced_native::Widget<Message, Renderer>,
     T: 'a,
     Message: 'a,
 {
-    fn into(self) -> iced_native::Element<'a, Message, Renderer> {
-        iced_native::Element::new(self)
+    fn from(widget: RightClickable<T, Message>) -> Self {
+        iced_native::Element::new(widget)
     }
 }
@@ -0,0 +1,550 @@
+//! A small CDCL SAT solver, with native support for cardinality constraints,
+//! which is what minesweeper clues are made of.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A variable or its negation.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Lit(u32);
+
+impl Lit {
+    pub fn new(var: usize, is_true: bool) -> Self {
+        Self((var as u32) << 1 | !is_true as u32)
+    }
+
+    fn var(self) -> usize {
+        (self.0 >> 1) as usize
+    }
+
+    fn is_negated(self) -> bool {
+        self.0 & 1 == 1
+    }
+
+    pub fn negate(self) -> Self {
+        Self(self.0 ^ 1)
+    }
+
+    fn idx(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Why a variable has its value.
+#[derive(Copy, Clone)]
+enum Reason {
+    Decision,
+    Clause(u32),
+    AtMost(u32),
+}
+
+/// At most "bound" of the literals can be true.
+struct AtMost {
+    lits: Vec<Lit>,
+    bound: u32,
+    /// How many of the literals were found to be true by the propagation.
+    trues: u32,
+}
+
+/// A conflict, given by the literals that can not all be false together.
+type Conflict = Vec<Lit>;
+
+pub struct Solver {
+    values: Vec<Option<bool>>,
+    levels: Vec<u32>,
+    reasons: Vec<Reason>,
+    trail_pos: Vec<u32>,
+
+    trail: Vec<Lit>,
+    /// Where each decision level starts in the trail.
+    trail_lim: Vec<usize>,
+    /// How much of the trail has been propagated.
+    qhead: usize,
+
+    clauses: Vec<Vec<Lit>>,
+    /// For each literal, the clauses watching it.
+    watches: Vec<Vec<u32>>,
+    at_mosts: Vec<AtMost>,
+    /// For each literal, the cardinality constraints it takes part in.
+    occurs: Vec<Vec<u32>>,
+
+    activity: Vec<f64>,
+    var_inc: f64,
+    is_unsat: bool,
+}
+
+impl Solver {
+    const VAR_DECAY: f64 = 0.95;
+    const FIRST_RESTART: u64 = 100;
+    const RESTART_GROWTH: f64 = 1.5;
+
+    pub fn new(var_count: usize) -> Self {
+        Self {
+            values: vec![None; var_count],
+            levels: vec![0; var_count],
+            reasons: vec![Reason::Decision; var_count],
+            trail_pos: vec![0; var_count],
+            trail: Vec::with_capacity(var_count),
+            trail_lim: Vec::new(),
+            qhead: 0,
+            clauses: Vec::new(),
+            watches: vec![Vec::new(); var_count * 2],
+            at_mosts: Vec::new(),
+            occurs: vec![Vec::new(); var_count * 2],
+            activity: vec![0.0; var_count],
+            var_inc: 1.0,
+            is_unsat: false,
+        }
+    }
+
+    /// Requires at most "bound" of the literals to be true.
+    ///
+    /// Must be called before solve().
+    pub fn add_at_most(&mut self, lits: Vec<Lit>, bound: usize) {
+        if bound >= lits.len() {
+            return;
+        }
+
+        let idx = self.at_mosts.len() as u32;
+        for lit in lits.iter() {
+            self.occurs[lit.idx()].push(idx);
+        }
+        self.at_mosts.push(AtMost {
+            lits,
+            bound: bound as u32,
+            trues: 0,
+        });
+    }
+
+    /// Requires exactly "count" of the literals to be true.
+    pub fn add_exactly(&mut self, lits: Vec<Lit>, count: usize) {
+        if count > lits.len() {
+            self.is_unsat = true;
+            return;
+        }
+
+        let negated = lits.iter().map(|lit| lit.negate()).collect();
+        self.add_at_most(negated, lits.len() - count);
+        self.add_at_most(lits, count);
+    }
+
+    /// Finds an assignment to the variables satisfying all the constraints,
+    /// where all the assumed literals are true.
+    ///
+    /// The variables are picked by how much they took part in conflicts, and
+    /// their values are chosen by "pick_value", so the caller can randomize
+    /// which solution is found.
+    pub fn solve(
+        &mut self,
+        assumptions: &[Lit],
+        mut pick_value: impl FnMut(usize) -> bool,
+        stop: &AtomicBool,
+    ) -> Option<Vec<bool>> {
+        if self.is_unsat {
+            return None;
+        }
+
+        // Constraints with bound 0 are never triggered by propagation:
+        for idx in 0..self.at_mosts.len() {
+            if self.at_mosts[idx].bound == 0 {
+                for i in 0..self.at_mosts[idx].lits.len() {
+                    let lit = self.at_mosts[idx].lits[i].negate();
+                    if !self.enqueue(lit, Reason::AtMost(idx as u32)) {
+                        return None;
+                    }
+                }
+            }
+        }
+
+        let mut conflicts = 0u64;
+        let mut next_restart = Self::FIRST_RESTART;
+
+        loop {
+            if let Some(conflict) = self.propagate() {
+                if self.trail_lim.is_empty() {
+                    return None;
+                }
+
+                let (learnt, backjump) = self.analyze(conflict);
+                self.cancel_until(backjump);
+                self.learn(learnt);
+
+                self.var_inc /= Self::VAR_DECAY;
+                conflicts += 1;
+                if stop.load(Ordering::Relaxed) {
+                    return None;
+                }
+                if conflicts == next_restart {
+                    next_restart += (next_restart as f64 * Self::RESTART_GROWTH) as u64;
+                    self.cancel_until(0);
+                }
+            } else if self.trail_lim.len() < assumptions.len() {
+                // Assumptions are always the first decisions:
+                let lit = assumptions[self.trail_lim.len()];
+                match self.lit_value(lit) {
+                    Some(false) => return None,
+                    Some(true) => self.trail_lim.push(self.trail.len()),
+                    None => {
+                        self.trail_lim.push(self.trail.len());
+                        self.enqueue(lit, Reason::Decision);
+                    }
+                }
+            } else if let Some(var) = self.pick_var() {
+                self.trail_lim.push(self.trail.len());
+                self.enqueue(Lit::new(var, pick_value(var)), Reason::Decision);
+            } else {
+                return Some(self.values.iter().map(|val| val.unwrap()).collect());
+            }
+        }
+    }
+
+    fn lit_value(&self, lit: Lit) -> Option<bool> {
+        self.values[lit.var()].map(|val| val != lit.is_negated())
+    }
+
+    /// Makes the literal true. Returns false if it was already false.
+    fn enqueue(&mut self, lit: Lit, reason: Reason) -> bool {
+        match self.lit_value(lit) {
+            Some(val) => val,
+            None => {
+                let var = lit.var();
+                self.values[var] = Some(!lit.is_negated());
+                self.levels[var] = self.trail_lim.len() as u32;
+                self.reasons[var] = reason;
+                self.trail_pos[var] = self.trail.len() as u32;
+                self.trail.push(lit);
+                true
+            }
+        }
+    }
+
+    fn propagate(&mut self) -> Option<Conflict> {
+        while self.qhead < self.trail.len() {
+            let lit = self.trail[self.qhead];
+            self.qhead += 1;
+
+            // Count the literal in every cardinality constraint before
+            // checking them, so that cancel_until() can undo it precisely.
+            for idx in self.occurs[lit.idx()].iter() {
+                self.at_mosts[*idx as usize].trues += 1;
+            }
+            for i in 0..self.occurs[lit.idx()].len() {
+                let idx = self.occurs[lit.idx()][i];
+                if let Some(conflict) = self.propagate_at_most(idx) {
+                    return Some(conflict);
+                }
+            }
+
+            if let Some(conflict) = self.propagate_clauses(lit.negate()) {
+                return Some(conflict);
+            }
+        }
+        None
+    }
+
+    fn propagate_at_most(&mut self, idx: u32) -> Option<Conflict> {
+        let constraint = &self.at_mosts[idx as usize];
+        if constraint.trues < constraint.bound {
+            return None;
+        }
+
+        if constraint.trues > constraint.bound {
+            return Some(
+                constraint
+                    .lits
+                    .iter()
+                    .filter(|lit| self.lit_value(**lit) == Some(true))
+                    .map(|lit| lit.negate())
+                    .collect(),
+            );
+        }
+
+        // The bound was reached, so everything else must be false:
+        for i in 0..self.at_mosts[idx as usize].lits.len() {
+            let lit = self.at_mosts[idx as usize].lits[i];
+            if self.lit_value(lit).is_none() {
+                self.enqueue(lit.negate(), Reason::AtMost(idx));
+            }
+        }
+        None
+    }
+
+    /// Visits the clauses watching a literal that just became false.
+    fn propagate_clauses(&mut self, false_lit: Lit) -> Option<Conflict> {
+        let mut watchers = std::mem::take(&mut self.watches[false_lit.idx()]);
+
+        let mut i = 0;
+        let mut conflict = None;
+        while i < watchers.len() {
+            let idx = watchers[i] as usize;
+
+            // Keep the false literal in the second position:
+            if self.clauses[idx][0] == false_lit {
+                self.clauses[idx].swap(0, 1);
+            }
+
+            let first = self.clauses[idx][0];
+            if self.lit_value(first) == Some(true) {
+                i += 1;
+                continue;
+            }
+
+            // Look for a new literal to watch:
+            let replacement = (2..self.clauses[idx].len())
+                .find(|k| self.lit_value(self.clauses[idx][*k]) != Some(false));
+            if let Some(k) = replacement {
+                self.clauses[idx].swap(1, k);
+                let watched = self.clauses[idx][1];
+                self.watches[watched.idx()].push(idx as u32);
+                watchers.swap_remove(i);
+                continue;
+            }
+
+            // The clause is unit or conflicting:
+            if !self.enqueue(first, Reason::Clause(idx as u32)) {
+                conflict = Some(self.clauses[idx].clone());
+                break;
+            }
+            i += 1;
+        }
+
+        self.watches[false_lit.idx()].append(&mut watchers);
+        conflict
+    }
+
+    /// The other literals of the constraint that implied the variable's
+    /// value, all of them false.
+    fn reason_lits(&self, var: usize) -> Vec<Lit> {
+        match self.reasons[var] {
+            Reason::Decision => Vec::new(),
+            Reason::Clause(idx) => self.clauses[idx as usize]
+                .iter()
+                .filter(|lit| lit.var() != var)
+                .copied()
+                .collect(),
+            Reason::AtMost(idx) => {
+                let pos = self.trail_pos[var];
+                self.at_mosts[idx as usize]
+                    .lits
+                    .iter()
+                    .filter(|lit| {
+                        lit.var() != var
+                            && self.lit_value(**lit) == Some(true)
+                            && self.trail_pos[lit.var()] < pos
+                    })
+                    .map(|lit| lit.negate())
+                    .collect()
+            }
+        }
+    }
+
+    /// Finds the first unique implication point of the conflict, returning
+    /// the learnt clause and the level to backjump to.
+    fn analyze(&mut self, conflict: Conflict) -> (Vec<Lit>, usize) {
+        let level = self.trail_lim.len() as u32;
+        let mut seen = vec![false; self.values.len()];
+        let mut learnt = vec![Lit(0)];
+        let mut pending = 0;
+        let mut lits = conflict;
+        let mut idx = self.trail.len();
+
+        let uip = loop {
+            for lit in lits {
+                let var = lit.var();
+                if seen[var] || self.levels[var] == 0 {
+                    continue;
+                }
+                seen[var] = true;
+                self.bump(var);
+
+                if self.levels[var] == level {
+                    pending += 1;
+                } else {
+                    learnt.push(lit);
+                }
+            }
+
+            // Next literal of the current level to be expanded:
+            loop {
+                idx -= 1;
+                if seen[self.trail[idx].var()] {
+                    break;
+                }
+            }
+            let lit = self.trail[idx];
+            pending -= 1;
+            if pending == 0 {
+                break lit;
+            }
+            lits = self.reason_lits(lit.var());
+        };
+        learnt[0] = uip.negate();
+
+        // Backjump to the deepest level among the other literals, which
+        // goes in second place to be watched:
+        let mut backjump = 0;
+        if learnt.len() > 1 {
+            let (max_i, _) = learnt
+                .iter()
+                .enumerate()
+                .skip(1)
+                .max_by_key(|(_, lit)| self.levels[lit.var()])
+                .unwrap();
+            learnt.swap(1, max_i);
+            backjump = self.levels[learnt[1].var()] as usize;
+        }
+
+        (learnt, backjump)
+    }
+
+    fn learn(&mut self, learnt: Vec<Lit>) {
+        let idx = self.clauses.len() as u32;
+        let asserting = learnt[0];
+        if learnt.len() > 1 {
+            self.watches[learnt[0].idx()].push(idx);
+            self.watches[learnt[1].idx()].push(idx);
+        }
+        self.clauses.push(learnt);
+        self.enqueue(asserting, Reason::Clause(idx));
+    }
+
+    fn cancel_until(&mut self, level: usize) {
+        if self.trail_lim.len() <= level {
+            return;
+        }
+
+        let start = self.trail_lim[level];
+        for pos in (start..self.trail.len()).rev() {
+            let lit = self.trail[pos];
+            if pos < self.qhead {
+                for idx in self.occurs[lit.idx()].iter() {
+                    self.at_mosts[*idx as usize].trues -= 1;
+                }
+            }
+            self.values[lit.var()] = None;
+        }
+
+        self.trail.truncate(start);
+        self.trail_lim.truncate(level);
+        self.qhead = start;
+    }
+
+    fn bump(&mut self, var: usize) {
+        self.activity[var] += self.var_inc;
+        if self.activity[var] > 1e100 {
+            for act in self.activity.iter_mut() {
+                *act *= 1e-100;
+            }
+            self.var_inc *= 1e-100;
+        }
+    }
+
+    fn pick_var(&self) -> Option<usize> {
+        (0..self.values.len())
+            .filter(|var| self.values[*var].is_none())
+            .max_by(|a, b| self.activity[*a].total_cmp(&self.activity[*b]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The variable 0, left for the tests to use.
+    const ESCAPE: Lit = Lit(0);
+
+    /// The variable of pigeon "p" being in hole "h".
+    fn in_hole(holes: usize, p: usize, h: usize) -> Lit {
+        Lit::new(1 + p * holes + h, true)
+    }
+
+    /// Every pigeon in exactly one hole, and at most one in each hole. If
+    /// "escape", the last pigeon can go instead to ESCAPE.
+    fn pigeonhole(pigeons: usize, holes: usize, escape: bool) -> Solver {
+        let mut solver = Solver::new(1 + pigeons * holes);
+        for p in 0..pigeons {
+            let mut lits: Vec<Lit> = (0..holes).map(|h| in_hole(holes, p, h)).collect();
+            if escape && p == pigeons - 1 {
+                lits.push(ESCAPE);
+            }
+            solver.add_exactly(lits, 1);
+        }
+        for h in 0..holes {
+            solver.add_at_most((0..pigeons).map(|p| in_hole(holes, p, h)).collect(), 1);
+        }
+        solver
+    }
+
+    fn solve(solver: &mut Solver, assumptions: &[Lit]) -> Option<Vec<bool>> {
+        solver.solve(assumptions, |_| true, &AtomicBool::new(false))
+    }
+
+    #[test]
+    fn solves_trivial_instances() {
+        let mut solver = Solver::new(3);
+        let model = solver.solve(&[], |var| var == 1, &AtomicBool::new(false));
+        assert_eq!(model, Some(vec![false, true, false]));
+
+        let (a, b) = (Lit::new(0, true), Lit::new(1, true));
+        let mut solver = Solver::new(2);
+        solver.add_exactly(vec![a, b], 3);
+        assert_eq!(solve(&mut solver, &[]), None);
+
+        let mut solver = Solver::new(2);
+        solver.add_exactly(vec![a, b], 1);
+        assert_eq!(solve(&mut solver, &[a.negate()]), Some(vec![false, true]));
+        let mut solver = Solver::new(2);
+        solver.add_exactly(vec![a, b], 1);
+        assert_eq!(solve(&mut solver, &[a.negate(), b.negate()]), None);
+    }
+
+    #[test]
+    fn at_most_at_the_bounds() {
+        let lits: Vec<Lit> = (0..4).map(|var| Lit::new(var, true)).collect();
+        let with = |add: fn(&mut Solver, Vec<Lit>, usize), bound| {
+            let mut solver = Solver::new(4);
+            add(&mut solver, lits.clone(), bound);
+            solve(&mut solver, &[])
+        };
+
+        assert_eq!(with(Solver::add_at_most, 0), Some(vec![false; 4]));
+        assert_eq!(with(Solver::add_at_most, 4), Some(vec![true; 4]));
+        assert_eq!(with(Solver::add_exactly, 0), Some(vec![false; 4]));
+        assert_eq!(with(Solver::add_exactly, 4), Some(vec![true; 4]));
+        let model = with(Solver::add_at_most, 1).unwrap();
+        assert_eq!(model.iter().filter(|val| **val).count(), 1);
+
+        // A literal assumed true against a bound of 0:
+        let mut solver = Solver::new(4);
+        solver.add_at_most(lits.clone(), 0);
+        assert_eq!(solve(&mut solver, &[lits[2]]), None);
+    }
+
+    #[test]
+    fn learns_from_conflicts() {
+        // Only unsatisfiable after trying the ways to place the pigeons:
+        let mut solver = pigeonhole(4, 3, false);
+        assert_eq!(solve(&mut solver, &[]), None);
+        assert!(!solver.clauses.is_empty());
+
+        // The escape is decided last, so the pigeons must be found not to
+        // fit first:
+        let mut solver = pigeonhole(4, 3, true);
+        let model = solve(&mut solver, &[]).unwrap();
+        assert!(!solver.clauses.is_empty());
+        assert!(model[ESCAPE.var()]);
+        for h in 0..3 {
+            let pigeons = (0..4).filter(|p| model[in_hole(3, *p, h).var()]).count();
+            assert_eq!(pigeons, 1);
+        }
+        for p in 0..3 {
+            let holes = (0..3).filter(|h| model[in_hole(3, p, *h).var()]).count();
+            assert_eq!(holes, 1);
+        }
+    }
+
+    #[test]
+    fn stops_when_asked() {
+        let mut solver = pigeonhole(4, 3, true);
+        assert_eq!(solver.solve(&[], |_| true, &AtomicBool::new(true)), None);
+    }
+}
@@ -0,0 +1,231 @@
+//! Saving a game in progress, to resume it later.
+//!
+//! Everything the game and the solver know is saved, so resuming doesn't
+//! need to solve anything again, and the random generator goes on as if the
+//! game was never interrupted.
+
+use super::minefield::Minefield;
+use super::replay::Recording;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// First bytes of every saved game.
+const MAGIC: &[u8] = b"mineswapper save\n";
+
+/// Version of the format, changed whenever what is saved changes.
+const VERSION: u32 = 3;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    /// The file is not a saved game.
+    NotASave,
+    /// The game was saved by a version of the format this one can't read.
+    Version(u32),
+    /// The saved game is corrupt.
+    Format(bincode::Error),
+}
+
+impl From<io::Error> for SaveError {
+    fn from(err: io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+impl From<bincode::Error> for SaveError {
+    fn from(err: bincode::Error) -> Self {
+        SaveError::Format(err)
+    }
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "{}", err),
+            SaveError::NotASave => write!(f, "not a saved game"),
+            SaveError::Version(version) => {
+                write!(
+                    f,
+                    "saved by an unsupported version {} of the format",
+                    version
+                )
+            }
+            SaveError::Format(err) => write!(f, "corrupt saved game: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveError::Io(err) => Some(err),
+            SaveError::Format(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A game in progress, as it was when saved.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedGame {
+    pub minefield: Minefield,
+    pub rng: rand_xoshiro::Xoshiro256StarStar,
+    /// How long the game was played before being saved.
+    pub elapsed: Duration,
+    /// The moves made so far, so the recording goes on after resuming.
+    pub recording: Recording,
+}
+
+impl SavedGame {
+    pub fn write_to(&self, mut out: impl Write) -> Result<(), SaveError> {
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut out, self)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    pub fn read_from(mut input: impl Read) -> Result<Self, SaveError> {
+        let mut magic = [0u8; MAGIC.len()];
+        input
+            .read_exact(&mut magic)
+            .map_err(|_| SaveError::NotASave)?;
+        if magic != MAGIC {
+            return Err(SaveError::NotASave);
+        }
+
+        let mut version = [0u8; 4];
+        input
+            .read_exact(&mut version)
+            .map_err(|_| SaveError::NotASave)?;
+        let version = u32::from_le_bytes(version);
+        if version != VERSION {
+            return Err(SaveError::Version(version));
+        }
+
+        Ok(bincode::deserialize_from(input)?)
+    }
+
+    /// Saves the game to the file, replacing it only once completely
+    /// written, so that a failure doesn't lose the previous save.
+    pub fn save(&self, path: &Path) -> Result<(), SaveError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("tmp");
+        self.write_to(io::BufWriter::new(fs::File::create(&temp)?))?;
+        fs::rename(temp, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SaveError> {
+        Self::read_from(io::BufReader::new(fs::File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::minefield::Tile;
+    use crate::verify;
+
+    /// The hidden tile least likely to hold a mine, as a careful player
+    /// would reveal, which is never a certain loss.
+    fn safest_tile(minefield: &mut Minefield) -> (u16, u16) {
+        let probabilities = minefield.mine_probabilities();
+        let mut hidden = Vec::new();
+        for (row, tiles) in (0u16..).zip(minefield.grid.rows()) {
+            for (col, tile) in (0u16..).zip(tiles.iter()) {
+                if let Tile::Hidden(..) = tile {
+                    hidden.push((row, col));
+                }
+            }
+        }
+        hidden
+            .into_iter()
+            .min_by(|a, b| {
+                let prob = |(row, col): (u16, u16)| *probabilities.get(row, col);
+                prob(*a).total_cmp(&prob(*b))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn resumes_the_same_game() {
+        for backend in ["diagram", "enumerator", "sat"] {
+            let recording = Recording::new([7; 32], 16, 16, 50, "square", backend);
+            let (mut minefield, mut rng) = recording.start().unwrap();
+
+            // Half the moves before saving, half after:
+            for _ in 0..10 {
+                let (row, col) = safest_tile(&mut minefield);
+                assert!(minefield.reveal(&mut rng, row, col).unwrap());
+            }
+
+            let saved = SavedGame {
+                minefield: minefield.clone(),
+                rng: rng.clone(),
+                elapsed: Duration::from_secs(42),
+                recording,
+            };
+            let mut file = Vec::new();
+            saved.write_to(&mut file).unwrap();
+            let mut resumed = SavedGame::read_from(&file[..]).unwrap();
+            assert_eq!(resumed.elapsed, saved.elapsed);
+            resumed.minefield.check_invariants().unwrap();
+
+            for _ in 0..10 {
+                if minefield.is_all_revealed() {
+                    break;
+                }
+                let (row, col) = safest_tile(&mut minefield);
+                assert!(minefield.reveal(&mut rng, row, col).unwrap());
+                assert!(resumed
+                    .minefield
+                    .reveal(&mut resumed.rng, row, col)
+                    .unwrap());
+                assert_eq!(verify::dump(&minefield), verify::dump(&resumed.minefield));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_saves() {
+        let recording = Recording::new([7; 32], 9, 9, 10, "square", "diagram");
+        let (mut minefield, mut rng) = recording.start().unwrap();
+        assert!(minefield.reveal(&mut rng, 4, 4).unwrap());
+        let saved = SavedGame {
+            minefield,
+            rng,
+            elapsed: Duration::from_secs(1),
+            recording,
+        };
+        let mut file = Vec::new();
+        saved.write_to(&mut file).unwrap();
+
+        file.truncate(file.len() / 2);
+        assert!(matches!(
+            SavedGame::read_from(&file[..]),
+            Err(SaveError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(matches!(
+            SavedGame::read_from(&b"mineswapper replay 1\n"[..]),
+            Err(SaveError::NotASave)
+        ));
+
+        let mut file = MAGIC.to_vec();
+        file.extend_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            SavedGame::read_from(&file[..]),
+            Err(SaveError::Version(_))
+        ));
+    }
+}
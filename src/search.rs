@@ -1,86 +1,491 @@
 use bitvec::prelude as bv;
-use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Clue {
     pub mine_count: u8,
-    pub adjacency: Vec<u16>,
+    pub adjacency: Vec<u32>,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Topology {
-    pub unknown_count: u16,
+    pub unknown_count: u32,
     pub clues: Vec<Clue>,
 }
 
-pub fn find_solutions(topology: &Topology) -> VecDeque<bv::BitVec> {
-    // Create a reverse map of unknows to the clues:
-    let unknowns_to_clues = {
-        let mut unknowns_to_clues = vec![Vec::<u16>::new(); topology.unknown_count as usize];
+/// Running tally of a clue during the search.
+#[derive(Copy, Clone)]
+struct ClueState {
+    mines: u8,
+    unassigned: u8,
+}
+
+/// Depth-first enumeration of all the solutions of a topology.
+///
+/// Only the current partial assignment is kept in memory, so the memory used
+/// is proportional to the number of unknowns, no matter how many solutions
+/// there are. Each solution is indexed by the unknown ids of the topology.
+pub struct Solutions<'a> {
+    topology: &'a Topology,
+    unknowns_to_clues: Vec<Vec<u32>>,
+    /// Order in which the unknowns are assigned.
+    order: Vec<u32>,
+    clue_states: Vec<ClueState>,
+    assignment: bv::BitVec,
+    /// Value assigned to each unknown in order, up to the current depth.
+    stack: bv::BitVec,
+    started: bool,
+    done: bool,
+    /// When set, the enumeration ends early.
+    stop: Option<&'a AtomicBool>,
+}
+
+impl<'a> Solutions<'a> {
+    pub fn new(topology: &'a Topology) -> Self {
+        // Create a reverse map of unknows to the clues:
+        let mut unknowns_to_clues = vec![Vec::<u32>::new(); topology.unknown_count as usize];
         for (i, clue) in topology.clues.iter().enumerate() {
             for unknown in &clue.adjacency {
-                unknowns_to_clues[*unknown as usize].push(i as u16);
+                unknowns_to_clues[*unknown as usize].push(i as u32);
             }
         }
 
-        unknowns_to_clues
-    };
+        let clue_states: Vec<ClueState> = topology
+            .clues
+            .iter()
+            .map(|clue| ClueState {
+                mines: 0,
+                unassigned: clue.adjacency.len() as u8,
+            })
+            .collect();
+
+        // A clue may already be impossible before anything is assigned.
+        let done = topology
+            .clues
+            .iter()
+            .any(|clue| clue.adjacency.len() < clue.mine_count as usize);
+
+        Self {
+            order: assignment_order(topology),
+            unknowns_to_clues,
+            clue_states,
+            assignment: bv::bitvec![0; topology.unknown_count as usize],
+            stack: bv::BitVec::with_capacity(topology.unknown_count as usize),
+            started: false,
+            done,
+            stop: None,
+            topology,
+        }
+    }
+
+    /// Makes the enumeration end as soon as "stop" is set, so that it can be
+    /// cancelled from another thread.
+    pub fn stop_when(mut self, stop: &'a AtomicBool) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Assigns a value to the next unknown in order, if consistent with all
+    /// its clues. Nothing is changed if it is not.
+    fn push(&mut self, is_mine: bool) -> bool {
+        let unknown = self.order[self.stack.len()] as usize;
+        let to_clues = &self.unknowns_to_clues[unknown];
 
-    // Find all solutions
-    let mut solutions = VecDeque::<bv::BitVec>::new();
-    solutions.push_back(bv::BitVec::new());
+        for clue_idx in to_clues {
+            let state = &self.clue_states[*clue_idx as usize];
+            let mines = state.mines + is_mine as u8;
+            let unassigned = state.unassigned - 1;
+            let expected = self.topology.clues[*clue_idx as usize].mine_count;
 
-    loop {
-        if let Some(mut sol) = solutions.pop_front() {
-            if sol.len() >= topology.unknown_count as usize {
-                // There should be only complete solutions remaining, return them.
-                solutions.push_front(sol);
-                break;
+            // Either more mines than needed, or not enough
+            // unknowns left to fulfill the clue:
+            if mines > expected || mines + unassigned < expected {
+                return false;
             }
+        }
+
+        for clue_idx in to_clues {
+            let state = &mut self.clue_states[*clue_idx as usize];
+            state.mines += is_mine as u8;
+            state.unassigned -= 1;
+        }
+        self.assignment.set(unknown, is_mine);
+        self.stack.push(is_mine);
+
+        true
+    }
+
+    /// Undoes the assignment of the last unknown, returning its value.
+    fn pop(&mut self) -> Option<bool> {
+        let is_mine = self.stack.pop()?;
+        let unknown = self.order[self.stack.len()] as usize;
 
-            let to_clues = &*unknowns_to_clues[sol.len()];
+        for clue_idx in &self.unknowns_to_clues[unknown] {
+            let state = &mut self.clue_states[*clue_idx as usize];
+            state.mines -= is_mine as u8;
+            state.unassigned += 1;
+        }
+
+        Some(is_mine)
+    }
 
-            sol.push(false);
-            if is_last_possible(&topology, to_clues, &sol) {
-                solutions.push_back(sol.clone());
+    /// Goes back to the deepest unknown that can still be switched to a mine,
+    /// and switches it.
+    fn backtrack(&mut self) -> bool {
+        while let Some(was_mine) = self.pop() {
+            if !was_mine && self.push(true) {
+                return true;
             }
-            sol.pop();
+        }
+        false
+    }
+}
 
-            sol.push(true);
-            if is_last_possible(&topology, to_clues, &sol) {
-                solutions.push_back(sol);
+impl<'a> Iterator for Solutions<'a> {
+    type Item = bv::BitVec;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        // Move away from the previously returned solution:
+        if self.started && !self.backtrack() {
+            self.done = true;
+            return None;
+        }
+        self.started = true;
+
+        loop {
+            if matches!(self.stop, Some(stop) if stop.load(Ordering::Relaxed)) {
+                self.done = true;
+                return None;
+            }
+
+            if self.stack.len() == self.order.len() {
+                return Some(self.assignment.clone());
+            }
+
+            if !self.push(false) && !self.push(true) && !self.backtrack() {
+                self.done = true;
+                return None;
             }
-        } else {
-            // Since the list is empty, solution is impossible.
-            break;
         }
     }
+}
+
+/// Orders the unknowns so that the most constrained clues are completed first.
+///
+/// Repeatedly take the clue with the fewest unknowns not yet ordered,
+/// preferring the ones sharing unknowns with what was already ordered, and
+/// then the ones with the fewest possible arrangements. This way, clues are
+/// fully assigned and dead ends are found as early as possible.
+pub fn assignment_order(topology: &Topology) -> Vec<u32> {
+    let mut order = Vec::with_capacity(topology.unknown_count as usize);
+    let mut is_ordered = bv::bitvec![0; topology.unknown_count as usize];
 
-    //println!("Tests count: {}", test_count);
+    let mut remaining: Vec<usize> = topology
+        .clues
+        .iter()
+        .map(|clue| clue.adjacency.len())
+        .collect();
+    let mut touched = bv::bitvec![0; topology.clues.len()];
 
-    solutions
+    let mut unknowns_to_clues = vec![Vec::<usize>::new(); topology.unknown_count as usize];
+    for (i, clue) in topology.clues.iter().enumerate() {
+        for unknown in &clue.adjacency {
+            unknowns_to_clues[*unknown as usize].push(i);
+        }
+    }
+
+    // Number of ways the mines of a clue can be arranged among its unknowns,
+    // saturated, because we only care about which is smaller.
+    let arrangements = |clue: &Clue| {
+        let n = clue.adjacency.len();
+        let k = std::cmp::min(
+            clue.mine_count as usize,
+            n.saturating_sub(clue.mine_count as usize),
+        );
+        (0..k).fold(1u64, |acc, i| {
+            acc.saturating_mul((n - i) as u64) / (i as u64 + 1)
+        })
+    };
+
+    while let Some(clue_idx) = (0..topology.clues.len())
+        .filter(|&i| remaining[i] > 0)
+        .min_by_key(|&i| (!touched[i], remaining[i], arrangements(&topology.clues[i])))
+    {
+        for unknown in &topology.clues[clue_idx].adjacency {
+            if is_ordered[*unknown as usize] {
+                continue;
+            }
+            is_ordered.set(*unknown as usize, true);
+            order.push(*unknown);
+
+            for other in &unknowns_to_clues[*unknown as usize] {
+                remaining[*other] -= 1;
+                touched.set(*other, true);
+            }
+        }
+    }
+
+    // Unknowns not referenced by any clue, if any, go last.
+    for unknown in 0..topology.unknown_count {
+        if !is_ordered[unknown as usize] {
+            order.push(unknown);
+        }
+    }
+
+    order
 }
 
-fn is_last_possible(topology: &Topology, to_clues: &[u16], sol: &bv::BitVec) -> bool {
-    for clue_idx in to_clues {
-        let mut mine_count = 0;
-        let mut unknown_count = 0;
-        let clue = &topology.clues[*clue_idx as usize];
-        for unk_idx in &clue.adjacency {
-            if let Some(is_mine) = sol.get(*unk_idx as usize) {
-                if *is_mine {
-                    mine_count += 1;
-                    if mine_count > clue.mine_count {
-                        // More mines than needed, impossible
-                        return false;
-                    }
-                }
-            } else {
-                unknown_count += 1;
+/// Splits the topology into its independent connected components.
+///
+/// Each component comes with the original id of each of its unknowns.
+fn split_components(topology: &Topology) -> Vec<(Topology, Vec<u32>)> {
+    // Union-find over the unknowns, joining the ones sharing a clue:
+    let mut parent: Vec<u32> = (0..topology.unknown_count).collect();
+    fn find(parent: &mut [u32], mut x: u32) -> u32 {
+        while parent[x as usize] != x {
+            parent[x as usize] = parent[parent[x as usize] as usize];
+            x = parent[x as usize];
+        }
+        x
+    }
+    for clue in topology.clues.iter() {
+        for pair in clue.adjacency.windows(2) {
+            let a = find(&mut parent, pair[0]);
+            let b = find(&mut parent, pair[1]);
+            parent[a as usize] = b;
+        }
+    }
+
+    // Renumber the unknowns of each component:
+    let mut components = Vec::new();
+    let mut component_of = HashMap::new();
+    let mut local_ids = vec![0u32; topology.unknown_count as usize];
+    for unknown in 0..topology.unknown_count {
+        let root = find(&mut parent, unknown);
+        let idx = *component_of.entry(root).or_insert_with(|| {
+            components.push((
+                Topology {
+                    unknown_count: 0,
+                    clues: Vec::new(),
+                },
+                Vec::new(),
+            ));
+            components.len() - 1
+        });
+        let (component, original_ids) = &mut components[idx];
+        local_ids[unknown as usize] = component.unknown_count;
+        component.unknown_count += 1;
+        original_ids.push(unknown);
+    }
+
+    for clue in topology.clues.iter() {
+        let adjacency = clue
+            .adjacency
+            .iter()
+            .map(|unknown| local_ids[*unknown as usize])
+            .collect();
+        if let Some(first) = clue.adjacency.first() {
+            let idx = component_of[&find(&mut parent, *first)];
+            components[idx].0.clues.push(Clue {
+                mine_count: clue.mine_count,
+                adjacency,
+            });
+        } else if clue.mine_count > 0 {
+            // A clue without unknowns can only be satisfied if zero.
+            components.push((
+                Topology {
+                    unknown_count: 0,
+                    clues: vec![Clue {
+                        mine_count: clue.mine_count,
+                        adjacency,
+                    }],
+                },
+                Vec::new(),
+            ));
+        }
+    }
+
+    components
+}
+
+/// Enumerates all the solutions of the topology, keeping only the first
+/// found for each number of mines.
+fn solutions_by_mine_count(topology: &Topology, stop: &AtomicBool) -> BTreeMap<usize, bv::BitVec> {
+    let mut by_count = BTreeMap::new();
+    for sol in Solutions::new(topology).stop_when(stop) {
+        by_count.entry(sol.count_ones()).or_insert(sol);
+    }
+    by_count
+}
+
+/// Finds a solution to the topology whose total number of mines is within
+/// "mine_range". Gives up, returning None, as soon as "stop" is set.
+pub fn find_solution_in_range(
+    topology: &Topology,
+    mine_range: RangeInclusive<usize>,
+    stop: &AtomicBool,
+) -> Option<bv::BitVec> {
+    let components: Vec<_> = split_components(topology)
+        .into_iter()
+        .map(|(component, original_ids)| (solutions_by_mine_count(&component, stop), original_ids))
+        .collect();
+    if stop.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    // For each total reachable by the first components, which mine count
+    // the last of them had to reach it:
+    let mut reachable: Vec<Vec<Option<usize>>> = vec![vec![Some(0)]];
+    for (by_count, original_ids) in components.iter() {
+        let last = reachable.last().unwrap();
+        let mut next = vec![None; last.len() + original_ids.len()];
+        for (total, _) in last.iter().enumerate().filter(|(_, r)| r.is_some()) {
+            for count in by_count.keys() {
+                next[total + count].get_or_insert(*count);
             }
         }
-        if unknown_count + mine_count < clue.mine_count {
-            // Not enough mines to fulfill the clue, impossible
-            return false;
+        reachable.push(next);
+    }
+
+    let mut total = mine_range
+        .into_iter()
+        .find(|total| matches!(reachable.last().unwrap().get(*total), Some(Some(_))))?;
+
+    let mut sol = bv::bitvec![0; topology.unknown_count as usize];
+    for (i, (by_count, original_ids)) in components.iter().enumerate().rev() {
+        let count = reachable[i + 1][total].unwrap();
+        for local_id in by_count[&count].iter_ones() {
+            sol.set(original_ids[local_id] as usize, true);
+        }
+        total -= count;
+    }
+
+    Some(sol)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topology(unknown_count: u32, clues: &[(u8, &[u32])]) -> Topology {
+        Topology {
+            unknown_count,
+            clues: clues
+                .iter()
+                .map(|(mine_count, adjacency)| Clue {
+                    mine_count: *mine_count,
+                    adjacency: adjacency.to_vec(),
+                })
+                .collect(),
         }
     }
-    true
+
+    /// Every assignment of the unknowns satisfying all the clues, by trying
+    /// them all.
+    fn brute_force(topology: &Topology) -> Vec<bv::BitVec> {
+        let n = topology.unknown_count as usize;
+        (0..1u32 << n)
+            .map(|bits| (0..n).map(|i| bits & (1 << i) != 0).collect::<bv::BitVec>())
+            .filter(|sol| {
+                topology.clues.iter().all(|clue| {
+                    let mines = clue.adjacency.iter().filter(|u| sol[**u as usize]).count();
+                    mines == clue.mine_count as usize
+                })
+            })
+            .collect()
+    }
+
+    fn sorted(mut solutions: Vec<bv::BitVec>) -> Vec<bv::BitVec> {
+        solutions.sort();
+        solutions
+    }
+
+    fn sorted_ids(ids: &[u32]) -> Vec<u32> {
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn counts_the_solutions() {
+        // Two mines among 0 to 3, and 4 free:
+        let free = topology(5, &[(2, &[0, 1, 2, 3])]);
+        assert_eq!(Solutions::new(&free).count(), 12);
+
+        // Either 2 is the only mine, or 3 and one of 0 and 1 are:
+        let overlapping = topology(4, &[(1, &[0, 1, 2]), (1, &[2, 3])]);
+        assert_eq!(Solutions::new(&overlapping).count(), 3);
+
+        let impossible = topology(2, &[(3, &[0, 1])]);
+        assert_eq!(Solutions::new(&impossible).count(), 0);
+    }
+
+    #[test]
+    fn finds_the_same_solutions_as_brute_force() {
+        let topologies = [
+            topology(0, &[]),
+            topology(3, &[]),
+            topology(3, &[(0, &[0, 1, 2])]),
+            // The 1-2-1 pattern, with the clues over a row of 5 unknowns:
+            topology(5, &[(1, &[0, 1, 2]), (2, &[1, 2, 3]), (1, &[2, 3, 4])]),
+            // A ring of clues, each over two unknowns:
+            topology(
+                6,
+                &[
+                    (1, &[0, 1]),
+                    (1, &[1, 2]),
+                    (1, &[2, 3]),
+                    (1, &[3, 4]),
+                    (1, &[4, 5]),
+                    (1, &[5, 0]),
+                ],
+            ),
+            // Clues disagreeing:
+            topology(3, &[(1, &[0, 1]), (2, &[0, 1]), (1, &[2])]),
+            topology(
+                8,
+                &[
+                    (2, &[0, 1, 2, 3]),
+                    (1, &[3, 4, 5]),
+                    (2, &[5, 6, 7]),
+                    (1, &[0, 7]),
+                ],
+            ),
+        ];
+        for topology in topologies.iter() {
+            let solutions = Solutions::new(topology).collect::<Vec<_>>();
+            let mut unique = solutions.clone();
+            unique.dedup();
+            assert_eq!(unique.len(), solutions.len());
+            assert_eq!(sorted(solutions), sorted(brute_force(topology)));
+        }
+    }
+
+    #[test]
+    fn stops_when_asked() {
+        let free = topology(5, &[(2, &[0, 1, 2, 3])]);
+        let stop = AtomicBool::new(true);
+        assert_eq!(Solutions::new(&free).stop_when(&stop).count(), 0);
+    }
+
+    #[test]
+    fn orders_the_most_constrained_first() {
+        let topology = topology(8, &[(1, &[0, 1, 2, 3]), (1, &[4, 5]), (1, &[5, 6])]);
+        let order = assignment_order(&topology);
+        assert_eq!(sorted_ids(&order), (0..8).collect::<Vec<_>>());
+
+        // The smallest clue first, then the one sharing an unknown with it,
+        // and the unknown without clues last:
+        assert_eq!(sorted_ids(&order[..2]), [4, 5]);
+        assert_eq!(order[2], 6);
+        assert_eq!(sorted_ids(&order[3..7]), [0, 1, 2, 3]);
+        assert_eq!(order[7], 7);
+    }
 }
@@ -1,16 +1,25 @@
+use super::backend::Backend;
+use super::bdd::Bdd;
 use super::grid;
-use super::neighbor_iter::NeighborIterable;
+use super::mine_distribution::MineDistribution;
+use super::minefield::UserMarking;
+use super::neighbor_iter::{NeighborIterable, Neighbors};
+use super::notation::{Cell, Position};
 use super::search;
-use arrayvec::ArrayVec;
+use super::topology::Topology;
 use bitvec::prelude as bv;
 use itertools::izip;
-use rand;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
 use std::iter::FromIterator;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 
-type Key = (u8, u8);
+type Key = (u16, u16);
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 enum CellState {
     UnknownUnconstrained,
     UnknownConstrained,
@@ -19,41 +28,178 @@ enum CellState {
     Clue(u8),
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 struct GraphSolution {
-    tile_map: HashMap<Key, u16>,
-    alternatives: VecDeque<bv::BitVec>,
+    tile_map: HashMap<Key, u32>,
+    topology: search::Topology,
+    /// Every solution to the topology, if enumerated.
+    alternatives: Option<Bdd>,
 }
 
+impl GraphSolution {
+    fn alternatives(&self) -> &Bdd {
+        self.alternatives
+            .as_ref()
+            .expect("Alternatives must have been enumerated")
+    }
+
+    fn alternatives_mut(&mut self) -> &mut Bdd {
+        self.alternatives
+            .as_mut()
+            .expect("Alternatives must have been enumerated")
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 struct Counters {
-    unconstrained_cells: u16,
-    hidden_mines: u16,
+    unconstrained_cells: u32,
+    hidden_mines: u32,
 }
 
 impl grid::GridCounters<CellState> for Counters {
-    fn notify_change(&mut self, from: &CellState, to: &CellState) {
+    type Error = SolverError;
+
+    fn notify_change(&mut self, from: &CellState, to: &CellState) -> Result<(), SolverError> {
+        let mut counters = self.clone();
         match *from {
             CellState::UnknownUnconstrained => {
-                self.unconstrained_cells -= 1;
+                counters.unconstrained_cells -= 1;
             }
             CellState::Mine => {
                 // These states can never change
-                assert!(matches!(*from, CellState::Mine));
-                return;
+                return match *to {
+                    CellState::Mine => Ok(()),
+                    _ => Err(SolverError::InvalidChange),
+                };
             }
             _ => (),
         }
 
         match *to {
-            CellState::Mine => self.hidden_mines -= 1,
-            CellState::UnknownUnconstrained => panic!("Cell state can not be set to unconstrained"),
+            CellState::Mine => {
+                counters.hidden_mines = counters
+                    .hidden_mines
+                    .checked_sub(1)
+                    .ok_or(SolverError::MineCount)?;
+            }
+            CellState::UnknownUnconstrained => return Err(SolverError::InvalidChange),
             _ => (),
         }
+
+        *self = counters;
+        Ok(())
+    }
+}
+
+/// An inconsistency found by the solver. Either the solver has a bug, or it
+/// was told something impossible.
+#[derive(Debug, Clone)]
+pub enum SolverError {
+    /// A clue was added to a cell already revealed.
+    AlreadyRevealed(Key),
+    /// A clue was added to a cell known to be a mine.
+    ClueOnMine(Key),
+    /// The cell contradicts what is known about the cells around it.
+    Inconsistent(Key),
+    /// A cell was changed in an impossible way, like a mine becoming empty.
+    InvalidChange,
+    /// The counters don't match the cells.
+    Counters,
+    /// The mines don't add up to the total.
+    MineCount,
+}
+
+impl fmt::Display for SolverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolverError::AlreadyRevealed((row, col)) => {
+                write!(f, "cell ({}, {}) was revealed twice", row, col)
+            }
+            SolverError::ClueOnMine((row, col)) => {
+                write!(
+                    f,
+                    "cell ({}, {}) was revealed, but must be a mine",
+                    row, col
+                )
+            }
+            SolverError::Inconsistent((row, col)) => {
+                write!(f, "cell ({}, {}) contradicts its neighbors", row, col)
+            }
+            SolverError::InvalidChange => write!(f, "a cell changed in an impossible way"),
+            SolverError::Counters => write!(f, "the counters don't match the cells"),
+            SolverError::MineCount => write!(f, "the mines don't add up to the total"),
+        }
+    }
+}
+
+impl std::error::Error for SolverError {}
+
+/// A proof that some revealed cells could not all be empty.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LossExplanation {
+    /// Clues that together rule out the revealed cells being empty.
+    pub clues: Vec<Key>,
+    /// The revealed cells that could not all be empty.
+    pub revealed: Vec<Key>,
+    /// Whether the total number of mines is also needed for the proof.
+    pub uses_mine_count: bool,
+}
+
+/// A hidden cell whose content is the same in every consistent layout.
+#[derive(Clone)]
+pub struct Hint {
+    pub cell: Key,
+    pub is_mine: bool,
+    /// Clues that together force the cell to be what it is.
+    pub clues: Vec<Key>,
+    /// Whether the total number of mines is also needed for the proof.
+    pub uses_mine_count: bool,
+}
+
+/// How far the solver got, shared with other threads so they can watch it
+/// and cancel it.
+#[derive(Default)]
+pub struct Progress {
+    /// Number of graphs whose alternatives were found.
+    pub components_solved: AtomicUsize,
+    /// Number of alternatives found so far, among all graphs.
+    pub alternatives_found: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    /// Makes the solver give up as soon as possible. What it computes
+    /// afterwards is meaningless, and must be discarded.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Enumerates all the solutions of the topology, keeping count of them.
+    fn enumerate(&self, topology: &search::Topology) -> Bdd {
+        let alternatives = Bdd::new(topology, &self.cancelled);
+        let found: f64 = alternatives
+            .ln_counts_by_mines()
+            .map(|(_, ln_alts)| ln_alts.exp())
+            .sum();
+        self.alternatives_found
+            .fetch_add(found as usize, Ordering::Relaxed);
+        alternatives
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PartialSolution {
-    grid: grid::Grid<CellState, u8, Counters>,
+    grid: grid::Grid<CellState, u16, Counters>,
     graphs_solutions: Vec<GraphSolution>,
+    /// Cells that changed since the graphs were last solved. Graphs not
+    /// containing any of them are still valid.
+    changed: HashSet<Key>,
+    #[serde(with = "super::topology::serde_by_name")]
+    topology: Arc<dyn Topology>,
+    #[serde(with = "super::backend::serde_by_name")]
+    backend: Arc<dyn Backend>,
+    #[serde(skip)]
+    progress: Arc<Progress>,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -64,119 +210,150 @@ enum UpdateAction {
     ToEmpty,
 }
 
-struct CartesianProduct<T> {
-    curr: Vec<usize>,
-    basis: Vec<Vec<T>>,
-}
+impl PartialSolution {
+    pub fn new(
+        width: u16,
+        height: u16,
+        mine_count: u32,
+        topology: Arc<dyn Topology>,
+        backend: Arc<dyn Backend>,
+    ) -> Self {
+        let counters = Counters {
+            unconstrained_cells: width as u32 * height as u32,
+            hidden_mines: mine_count,
+        };
 
-impl<T> CartesianProduct<T> {
-    fn new(basis: impl IntoIterator<Item = impl IntoIterator<Item = T>>) -> Self {
-        let basis: Vec<Vec<T>> = basis.into_iter().map(|v| v.into_iter().collect()).collect();
         Self {
-            curr: vec![0; basis.len()],
-            basis,
+            grid: grid::Grid::new(width, height, counters, CellState::UnknownUnconstrained),
+            graphs_solutions: Vec::new(),
+            changed: HashSet::new(),
+            topology,
+            backend,
+            progress: Arc::default(),
         }
     }
-}
 
-impl<T> Iterator for CartesianProduct<T>
-where
-    T: Copy,
-{
-    type Item = Vec<T>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.curr.len() == 0 {
-            return None;
-        }
-        let ret = Some(
-            self.curr
-                .iter()
-                .enumerate()
-                .map(|(i, val)| self.basis[i][*val])
-                .collect(),
+    /// Creates the solver for the position, with the graphs already solved.
+    pub fn from_position(
+        position: &Position,
+        backend: Arc<dyn Backend>,
+    ) -> Result<Self, SolverError> {
+        let cells = &position.cells;
+        let mut sol = Self::new(
+            cells.width(),
+            cells.height(),
+            position.mine_count,
+            position.topology.clone(),
+            backend,
         );
-
-        for (val, v) in izip!(self.curr.iter_mut(), self.basis.iter()) {
-            *val += 1;
-            if *val != v.len() {
-                return ret;
-            }
-
-            *val = 0;
+        for (key, clue) in position.clues() {
+            sol.add_clue(key, clue)?;
         }
+        sol.find_graph_solutions()?;
+        Ok(sol)
+    }
 
-        // All combinations have been generated, so stop execution in next iteration.
-        self.curr.clear();
-
-        ret
+    /// The topology, to be shared with what is built from the solution.
+    pub fn shared_topology(&self) -> Arc<dyn Topology> {
+        self.topology.clone()
     }
-}
 
-impl PartialSolution {
-    pub fn new(width: u8, height: u8, mine_count: u16) -> Self {
-        let counters = Counters {
-            unconstrained_cells: width as u16 * height as u16,
-            hidden_mines: mine_count,
-        };
+    /// The board as the solver knows it, which is without the marks of the
+    /// player.
+    pub fn position(&self) -> Position {
+        let cells = self
+            .grid_keys()
+            .map(|(row, col)| match self.revealed_clue(row, col) {
+                Some(clue) => Cell::Revealed(clue),
+                None => Cell::Hidden(UserMarking::None),
+            })
+            .collect();
 
-        Self {
-            grid: grid::Grid::new(width, height, counters, CellState::UnknownUnconstrained),
-            graphs_solutions: Vec::new(),
+        Position {
+            mine_count: self.mine_total(),
+            topology: self.shared_topology(),
+            cells: grid::Grid::from_vec(self.grid.width(), self.grid.height(), (), cells)
+                .expect("There must be a cell for each tile"),
         }
     }
 
-    pub fn add_clue(&mut self, (row, col): Key, mut clue: u8) {
+    /// Whether the solver has already deduced that the cell is a mine, in
+    /// which case no layout of the mines ever moves it.
+    pub fn is_known_mine(&self, row: u16, col: u16) -> bool {
+        matches!(self.grid.get(row, col), CellState::Mine)
+    }
+
+    /// Reports the progress of the solver from now on to "progress".
+    pub fn track_progress(&mut self, progress: Arc<Progress>) {
+        self.progress = progress;
+    }
+
+    pub fn add_clue(&mut self, (row, col): Key, mut clue: u8) -> Result<(), SolverError> {
         let state = self.grid.get(row, col);
 
         // Check if we previously knew if the cell was empty,
         // and possibly update the state before anything else.
         match state {
-            CellState::Clue(_) => panic!("Can't add clue to a revealed square!"),
-            CellState::Mine => panic!("Can't add clue to a hidden mine!"),
+            CellState::Clue(_) => return Err(SolverError::AlreadyRevealed((row, col))),
+            CellState::Mine => return Err(SolverError::ClueOnMine((row, col))),
             CellState::UnknownConstrained => {
                 // We didn't knew this was empty, so we
                 // must update the neighboring cells.
-                self.breadth_first_update(UpdateAction::ToEmpty, &[(row, col)]);
+                self.breadth_first_update(UpdateAction::ToEmpty, &[(row, col)])?;
             }
             _ => {}
         }
 
-        let mut unknowns = ArrayVec::<Key, 8>::new();
+        let mut unknowns = Neighbors::<Key>::new();
 
         // Mark neighbors as constrained and check for known mines:
+        self.changed.insert((row, col));
         for (row, col) in self.neighbors_of(row, col) {
+            // The graphs around the new clue are joined by it:
+            self.changed.insert((row, col));
+
             let cell = self.grid.get(row, col);
             match cell {
                 CellState::UnknownUnconstrained => {
-                    self.grid.set(row, col, CellState::UnknownConstrained);
+                    self.grid.set(row, col, CellState::UnknownConstrained)?;
                     unknowns.push((row, col));
                 }
                 CellState::UnknownConstrained => {
                     unknowns.push((row, col));
                 }
                 CellState::Mine => {
-                    clue -= 1;
+                    clue = clue
+                        .checked_sub(1)
+                        .ok_or(SolverError::Inconsistent((row, col)))?;
                 }
                 _ => {}
             }
         }
 
         // Set the state of the new clue cell:
-        self.grid.set(row, col, CellState::Clue(clue));
+        if clue as usize > unknowns.len() {
+            return Err(SolverError::Inconsistent((row, col)));
+        }
+        self.grid.set(row, col, CellState::Clue(clue))?;
 
         // Update the solution, changing unknown neighbors to either Empty or Mines, as appropriate.
-        if unknowns.len() > 0 {
+        if !unknowns.is_empty() {
             let slice = unknowns.as_slice();
             if clue == 0 {
-                self.breadth_first_update(UpdateAction::ToEmpty, slice);
+                self.breadth_first_update(UpdateAction::ToEmpty, slice)?;
             } else if clue == unknowns.len() as u8 {
-                self.breadth_first_update(UpdateAction::ToMine, slice);
+                self.breadth_first_update(UpdateAction::ToMine, slice)?;
             }
         }
+
+        Ok(())
     }
 
-    fn breadth_first_update(&mut self, action: UpdateAction, seed: &[Key]) {
+    fn breadth_first_update(
+        &mut self,
+        action: UpdateAction,
+        seed: &[Key],
+    ) -> Result<(), SolverError> {
         let mut is_queued: HashSet<Key> = HashSet::from_iter(seed.iter().copied());
         let mut queue: VecDeque<(Key, UpdateAction)> =
             is_queued.iter().map(|key| (*key, action)).collect();
@@ -195,25 +372,28 @@ impl PartialSolution {
                     let clue = if let CellState::Clue(clue) = self.grid.get(row, col) {
                         *clue
                     } else {
-                        panic!("Must be a clue!");
+                        return Err(SolverError::Inconsistent((row, col)));
                     };
 
-                    let mut unknowns = ArrayVec::<Key, 8>::new();
+                    let mut unknowns = Neighbors::<Key>::new();
 
                     for (row, col) in self.neighbors_of(row, col) {
                         match self.grid.get(row, col) {
                             CellState::UnknownConstrained => unknowns.push((row, col)),
                             CellState::UnknownUnconstrained => {
-                                panic!("Can't have unconstrained next to a clue!")
+                                // Can't have unconstrained next to a clue!
+                                return Err(SolverError::Inconsistent((row, col)));
                             }
                             _ => (),
                         }
                     }
 
-                    assert!(unknowns.len() as u8 >= clue);
+                    if (unknowns.len() as u8) < clue {
+                        return Err(SolverError::Inconsistent((row, col)));
+                    }
 
                     if unknowns.len() as u8 == clue {
-                        self.grid.set(row, col, CellState::Clue(0));
+                        self.grid.set(row, col, CellState::Clue(0))?;
 
                         for (row, col) in unknowns {
                             try_enqueue((row, col), UpdateAction::ToMine);
@@ -222,11 +402,11 @@ impl PartialSolution {
                 }
 
                 UpdateAction::ToMine => {
-                    assert!(matches!(
-                        self.grid.get(row, col),
-                        CellState::UnknownConstrained
-                    ));
-                    self.grid.set(row, col, CellState::Mine);
+                    if !matches!(self.grid.get(row, col), CellState::UnknownConstrained) {
+                        return Err(SolverError::Inconsistent((row, col)));
+                    }
+                    self.grid.set(row, col, CellState::Mine)?;
+                    self.changed.insert((row, col));
 
                     for (row, col) in self.neighbors_of(row, col) {
                         match *self.grid.get(row, col) {
@@ -235,13 +415,15 @@ impl PartialSolution {
                                 if val == 0 {
                                     // A clue can only get to zero once,
                                     // so it can not be inserted twice:
-                                    assert!(is_queued.insert((row, col)));
+                                    if !is_queued.insert((row, col)) {
+                                        return Err(SolverError::Inconsistent((row, col)));
+                                    }
                                     queue.push_back((
                                         (row, col),
                                         UpdateAction::CheckIfClueFindEmpties,
                                     ));
                                 }
-                                self.grid.set(row, col, CellState::Clue(val));
+                                self.grid.set(row, col, CellState::Clue(val))?;
                             }
                             _ => (),
                         }
@@ -250,7 +432,9 @@ impl PartialSolution {
 
                 UpdateAction::CheckIfClueFindEmpties => {
                     // You can only get empties from a 0 clue:
-                    assert!(matches!(self.grid.get(row, col), CellState::Clue(0)));
+                    if !matches!(self.grid.get(row, col), CellState::Clue(0)) {
+                        return Err(SolverError::Inconsistent((row, col)));
+                    }
 
                     for (row, col) in self.neighbors_of(row, col) {
                         match self.grid.get(row, col) {
@@ -258,7 +442,8 @@ impl PartialSolution {
                                 try_enqueue((row, col), UpdateAction::ToEmpty)
                             }
                             CellState::UnknownUnconstrained => {
-                                panic!("Can't have unconstrained next to a clue!")
+                                // Can't have unconstrained next to a clue!
+                                return Err(SolverError::Inconsistent((row, col)));
                             }
                             _ => (),
                         }
@@ -267,11 +452,11 @@ impl PartialSolution {
 
                 UpdateAction::ToEmpty => {
                     // Only constrained can be found to be empty:
-                    assert!(matches!(
-                        self.grid.get(row, col),
-                        CellState::UnknownConstrained
-                    ));
-                    self.grid.set(row, col, CellState::Empty);
+                    if !matches!(self.grid.get(row, col), CellState::UnknownConstrained) {
+                        return Err(SolverError::Inconsistent((row, col)));
+                    }
+                    self.grid.set(row, col, CellState::Empty)?;
+                    self.changed.insert((row, col));
 
                     for (row, col) in self.neighbors_of(row, col) {
                         match *self.grid.get(row, col) {
@@ -284,13 +469,183 @@ impl PartialSolution {
                 }
             }
         }
+
+        Ok(())
     }
 
-    pub fn find_graph_solutions(&mut self) {
+    /// Deduces mines and empty cells from pairs of clues sharing unknowns,
+    /// like in the 1-1 and 1-2-1 patterns, until nothing else can be deduced.
+    ///
+    /// Only the clues around the cells changed since the graphs were last
+    /// solved are considered, because the others were already checked.
+    fn pattern_update(&mut self) -> Result<(), SolverError> {
+        let mut queue: VecDeque<Key> = VecDeque::new();
+        let mut is_queued = HashSet::new();
+        for key in self.clues_around(self.changed.iter().copied()) {
+            if is_queued.insert(key) {
+                queue.push_back(key);
+            }
+        }
+
+        while let Some(key) = queue.pop_front() {
+            is_queued.remove(&key);
+
+            let deductions = self.pair_deductions(key);
+            if deductions.is_empty() {
+                continue;
+            }
+
+            for &(cell, is_mine) in deductions.iter() {
+                // A previous deduction may have already propagated here:
+                if let CellState::UnknownConstrained = self.grid.get(cell.0, cell.1) {
+                    let action = if is_mine {
+                        UpdateAction::ToMine
+                    } else {
+                        UpdateAction::ToEmpty
+                    };
+                    self.breadth_first_update(action, &[cell])?;
+                }
+            }
+
+            // The clue may still be paired with others, and the clues
+            // around the deduced cells may now allow new deductions:
+            let cells = deductions.into_iter().map(|(cell, _)| cell);
+            for key in std::iter::once(key).chain(self.clues_around(cells)) {
+                if is_queued.insert(key) {
+                    queue.push_back(key);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The clues with mines yet to be found, among the cells and their
+    /// neighbors.
+    fn clues_around(&self, cells: impl Iterator<Item = Key>) -> Vec<Key> {
+        let mut clues = Vec::new();
+        for (row, col) in cells {
+            for key in std::iter::once((row, col)).chain(self.neighbors_of(row, col)) {
+                if let CellState::Clue(val) = self.grid.get(key.0, key.1) {
+                    if *val > 0 {
+                        clues.push(key);
+                    }
+                }
+            }
+        }
+        clues
+    }
+
+    /// Compares the clue with every other clue sharing unknowns with it,
+    /// and returns the unknowns whose content follows from the pair.
+    fn pair_deductions(&self, key: Key) -> Vec<(Key, bool)> {
+        let (mines, unknowns) = match self.clue_unknowns(key) {
+            Some(clue) => clue,
+            None => return Vec::new(),
+        };
+
+        let mut others: Vec<Key> = unknowns
+            .iter()
+            .flat_map(|&(row, col)| self.neighbors_of(row, col))
+            .filter(|other| *other != key)
+            .collect();
+        others.sort_unstable();
+        others.dedup();
+
+        let mut deductions = Vec::new();
+        for other in others {
+            let (other_mines, other_unknowns) = match self.clue_unknowns(other) {
+                Some(clue) => clue,
+                None => continue,
+            };
+
+            let shared = unknowns
+                .iter()
+                .filter(|unknown| other_unknowns.contains(unknown))
+                .count() as i32;
+            let (mines, other_mines) = (mines as i32, other_mines as i32);
+            let only = unknowns.len() as i32 - shared;
+            let other_only = other_unknowns.len() as i32 - shared;
+
+            // Bounds on the number of mines among the shared unknowns:
+            let max_shared = shared.min(mines).min(other_mines);
+            let min_shared = 0.max(mines - only).max(other_mines - other_only);
+
+            // The unknowns of each clue not shared with the other must hold
+            // the mines not in the shared ones:
+            for (clue_mines, clue_only, clue_unknowns, excluded) in [
+                (mines, only, &unknowns, &other_unknowns),
+                (other_mines, other_only, &other_unknowns, &unknowns),
+            ] {
+                let is_mine = if clue_only > 0 && clue_mines - max_shared == clue_only {
+                    true
+                } else if clue_only > 0 && clue_mines - min_shared == 0 {
+                    false
+                } else {
+                    continue;
+                };
+
+                deductions.extend(
+                    clue_unknowns
+                        .iter()
+                        .filter(|unknown| !excluded.contains(unknown))
+                        .map(|unknown| (*unknown, is_mine)),
+                );
+            }
+
+            if !deductions.is_empty() {
+                // The clue must be looked at again after the deductions:
+                break;
+            }
+        }
+
+        deductions
+    }
+
+    /// The number of mines yet to be found around a clue, and the unknown
+    /// cells where they can be. None if the cell is not such a clue.
+    fn clue_unknowns(&self, (row, col): Key) -> Option<(u8, Neighbors<Key>)> {
+        match self.grid.get(row, col) {
+            CellState::Clue(val) if *val > 0 => {
+                let unknowns = self
+                    .neighbors_of(row, col)
+                    .filter(|&(row, col)| {
+                        matches!(self.grid.get(row, col), CellState::UnknownConstrained)
+                    })
+                    .collect();
+                Some((*val, unknowns))
+            }
+            _ => None,
+        }
+    }
+
+    /// Updates the solutions of the graphs, solving again only the ones
+    /// whose cells changed since the last call.
+    pub fn find_graph_solutions(&mut self) -> Result<(), SolverError> {
+        self.pattern_update()?;
+
         let mut visited =
             vec![bv::bitvec![0; self.grid.width() as usize]; self.grid.height() as usize];
 
-        let mut graphs_solutions = Vec::new();
+        // Keep the graphs that didn't change, and mark their cells as visited
+        // so they are not extracted again:
+        let changed = std::mem::take(&mut self.changed);
+        let mut graphs_solutions = std::mem::take(&mut self.graphs_solutions);
+        graphs_solutions.retain(|sol| !sol.tile_map.keys().any(|key| changed.contains(key)));
+        for sol in graphs_solutions.iter() {
+            for &(row, col) in sol.tile_map.keys() {
+                visited[row as usize].set(col as usize, true);
+                for (row, col) in self.neighbors_of(row, col) {
+                    if let CellState::Clue(val) = self.grid.get(row, col) {
+                        if *val > 0 {
+                            visited[row as usize].set(col as usize, true);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut new_graphs = Vec::new();
         for (i, row) in self.grid.rows().enumerate() {
             for (j, cell) in row.iter().enumerate() {
                 let mut cell_visited = visited[i].get_mut(j).unwrap();
@@ -303,34 +658,54 @@ impl PartialSolution {
                         cell_visited.set(true);
                         drop(cell_visited);
 
-                        let (tile_map, topology) =
-                            self.extract_graph_starting_from(i as u8, j as u8, &mut visited[..]);
-                        let alternatives = search::find_solutions(&topology);
-                        graphs_solutions.push(GraphSolution {
-                            tile_map,
-                            alternatives,
-                        });
+                        new_graphs.push(self.extract_graph_starting_from(
+                            i as u16,
+                            j as u16,
+                            &mut visited[..],
+                        )?);
                     }
                     _ => (),
                 }
             }
         }
 
+        // The graphs are independent, so they are solved in parallel. The
+        // order they are found is kept, so the results don't depend on which
+        // thread finishes first:
+        graphs_solutions.par_extend(new_graphs.into_par_iter().map(|(tile_map, topology)| {
+            // Enumerating can take very long on hard boards, so
+            // leave it for when it is needed, if the backend can:
+            let alternatives = self
+                .backend
+                .enumerates_alternatives()
+                .then(|| self.progress.enumerate(&topology));
+            self.progress
+                .components_solved
+                .fetch_add(1, Ordering::Relaxed);
+            GraphSolution {
+                tile_map,
+                topology,
+                alternatives,
+            }
+        }));
+
         self.graphs_solutions = graphs_solutions;
+
+        Ok(())
     }
 
     fn extract_graph_starting_from(
         &self,
-        row: u8,
-        col: u8,
+        row: u16,
+        col: u16,
         visited: &mut [bv::BitVec],
-    ) -> (HashMap<Key, u16>, search::Topology) {
+    ) -> Result<(HashMap<Key, u32>, search::Topology), SolverError> {
         // Start search
         let mut queue = VecDeque::new();
         queue.push_back((row, col));
 
         // Maps of keys to the local 0-based indices of unknowns:
-        let mut unk_map = HashMap::<Key, u16>::new();
+        let mut unk_map = HashMap::<Key, u32>::new();
 
         // List of clues part of this graph:
         let mut clues = Vec::<search::Clue>::new();
@@ -346,13 +721,11 @@ impl PartialSolution {
             };
 
             match self.grid.get(row, col) {
-                CellState::Clue(val) => {
-                    assert!(*val > 0u8);
-
+                CellState::Clue(val) if *val > 0 => {
                     let mut adjacency = Vec::new();
                     for (row, col) in self.neighbors_of(row, col) {
                         if let CellState::UnknownConstrained = self.grid.get(row, col) {
-                            let len = unk_map.len() as u16;
+                            let len = unk_map.len() as u32;
                             let unk_id = *unk_map.entry((row, col)).or_insert(len);
                             adjacency.push(unk_id);
 
@@ -374,45 +747,49 @@ impl PartialSolution {
                         }
                     }
                 }
-                _ => panic!("Only constrained unknowns and clues must be part of a graph"),
+                // Only constrained unknowns and clues must be part of a graph:
+                _ => return Err(SolverError::Inconsistent((row, col))),
             }
         }
 
-        let unknown_count = unk_map.len() as u16;
-        (
+        let unknown_count = unk_map.len() as u32;
+        Ok((
             unk_map,
             search::Topology {
                 unknown_count,
                 clues,
             },
-        )
+        ))
     }
 
     /// Tries to find a valid configuration where all cells in "reveal" are empty.
     ///
     /// self is modified assuming this function will succeed, so in case of return false,
     /// this partial solution should no longer be used.
-    pub fn find_acomodating_solution(
+    ///
+    /// Errors from "reconfigure_tile" are passed on, along with the solver's own.
+    pub fn find_acomodating_solution<E: From<SolverError>>(
         &mut self,
         rng: &mut impl rand::Rng,
-        revealed: impl IntoIterator<Item = (u8, u8)>,
-        mut reconfigure_tile: impl FnMut(u8, u8, bool),
-    ) -> bool {
+        revealed: impl IntoIterator<Item = (u16, u16)>,
+        mut reconfigure_tile: impl FnMut(u16, u16, bool) -> Result<(), E>,
+    ) -> Result<bool, E> {
         let mut unconstrained_revealed = Vec::new();
 
+        // The revealed cells of each graph, which must be empty:
+        let mut assumptions = vec![Vec::new(); self.graphs_solutions.len()];
+
         for key in revealed {
             let cell = *self.grid.get(key.0, key.1);
             match cell {
                 CellState::UnknownConstrained => {
                     // Linear search through all the graphs (because there can't be many)
                     // for the one containing the key.
-                    for sol in self.graphs_solutions.iter_mut() {
+                    for (sol, graph_assumptions) in
+                        izip!(&self.graphs_solutions, assumptions.iter_mut())
+                    {
                         if let Some(idx) = sol.tile_map.get(&key) {
-                            // Delete every alternative who has a mine at idx:
-                            sol.alternatives.retain(|alt| !alt[*idx as usize]);
-                            if sol.alternatives.len() == 0 {
-                                return false;
-                            }
+                            graph_assumptions.push((*idx, false));
 
                             // Each key can be in at most 1 graph, so there is no
                             // need to search the others
@@ -422,103 +799,593 @@ impl PartialSolution {
                 }
                 CellState::UnknownUnconstrained => {
                     unconstrained_revealed.push(key);
-                    self.grid.set(key.0, key.1, CellState::Empty);
+                    self.grid.set(key.0, key.1, CellState::Empty)?;
                 }
                 CellState::Mine => {
-                    return false;
+                    return Ok(false);
                 }
-                CellState::Clue(_) => panic!("Tried to reveal an already revealed cell."),
+                CellState::Clue(_) => return Err(SolverError::AlreadyRevealed(key).into()),
                 CellState::Empty => (),
             }
         }
 
-        // Count the number of mines in each solution for each graph:
-        let mine_counts: Vec<HashMap<u16, Vec<&bv::BitVec>>> = self
-            .graphs_solutions
-            .iter()
-            .map(|sol| {
-                let mut counts: HashMap<u16, Vec<&bv::BitVec>> = HashMap::new();
-                for alt in sol.alternatives.iter() {
-                    let count = alt.count_ones() as u16;
-                    counts.entry(count).or_default().push(alt);
+        let layouts = if self.backend.enumerates_alternatives() {
+            for (sol, graph_assumptions) in izip!(self.graphs_solutions.iter_mut(), &assumptions) {
+                // Delete every alternative who has a mine at a revealed cell:
+                let alternatives = sol.alternatives_mut();
+                for (idx, is_mine) in graph_assumptions {
+                    alternatives.restrict(*idx, *is_mine);
                 }
-                counts
-            })
-            .collect();
-
-        let combinations = Vec::from_iter(
-            CartesianProduct::new(mine_counts.iter().map(|x| x.keys().copied())).filter(|comb| {
-                let total = comb.iter().copied().sum();
-
-                // Do we have enough remaining mines to satisfy this
-                // combination of solutions?
-                if self.grid.counters.hidden_mines < total {
-                    return false;
-                }
-
-                // Do we have enough unconstrained squares to fit all
-                // the mines left over from this solution?
-                if self.grid.counters.unconstrained_cells < self.grid.counters.hidden_mines - total
-                {
-                    return false;
+                if alternatives.is_empty() {
+                    return Ok(false);
                 }
+            }
+            self.sample_layouts(rng)
+        } else {
+            self.solve_layouts(&assumptions, rng)
+        };
 
-                true
-            }),
-        );
-
-        // TODO: calculate the probability of each combination actually happening,
-        // so that we have the weights to randomly select one solution.
-        // For now, just sample uniformly from the combinations, and then sample
-        // uniformly from graph solutions that makes up the combination:
-        use rand::seq::SliceRandom;
-        let mut replaced_mines = 0u16;
-        if let Some(combination) = combinations.as_slice().choose(rng) {
-            // Reconfigure constrained tiles
-            for (mine_count, sols_per_count, graph) in
-                izip!(combination, &mine_counts, &self.graphs_solutions)
-            {
-                replaced_mines += mine_count;
+        let layouts = if let Some(layouts) = layouts {
+            layouts
+        } else {
+            return Ok(false);
+        };
 
-                let sol = *sols_per_count
-                    .get(mine_count)
-                    .unwrap()
-                    .as_slice()
-                    .choose(rng)
-                    .unwrap();
+        // Reconfigure constrained tiles:
+        let mut replaced_mines = 0u32;
+        for (layout, graph) in izip!(&layouts, &self.graphs_solutions) {
+            replaced_mines += layout.count_ones() as u32;
 
-                for ((row, col), idx) in graph.tile_map.iter() {
-                    reconfigure_tile(*row, *col, sol[*idx as usize]);
-                }
+            for ((row, col), idx) in graph.tile_map.iter() {
+                reconfigure_tile(*row, *col, layout[*idx as usize])?;
             }
         }
 
         // Clear just revealed unconstrained tiles from mines:
         for (row, col) in unconstrained_revealed {
-            reconfigure_tile(row, col, false);
+            reconfigure_tile(row, col, false)?;
         }
 
         // Reconfigure unconstrained tiles:
-        assert!(self.grid.counters.hidden_mines >= replaced_mines);
-        let remaining_mines = self.grid.counters.hidden_mines - replaced_mines;
-
-        assert!(self.grid.counters.unconstrained_cells >= remaining_mines);
+        let remaining_mines = self
+            .grid
+            .counters
+            .hidden_mines
+            .checked_sub(replaced_mines)
+            .filter(|remaining| *remaining <= self.grid.counters.unconstrained_cells)
+            .ok_or(SolverError::MineCount)?;
         let mut shuffled_mines = vec![true; remaining_mines as usize];
         shuffled_mines.resize(self.grid.counters.unconstrained_cells as usize, false);
-        shuffled_mines.shuffle(rng);
+        rand::seq::SliceRandom::shuffle(&mut shuffled_mines[..], rng);
 
         for (i, row) in self.grid.rows().enumerate() {
             for (k, cell) in row.iter().enumerate() {
                 if let CellState::UnknownUnconstrained = cell {
-                    reconfigure_tile(i as u8, k as u8, shuffled_mines.pop().unwrap());
+                    let is_mine = shuffled_mines.pop().ok_or(SolverError::MineCount)?;
+                    reconfigure_tile(i as u16, k as u16, is_mine)?;
                 }
             }
         }
-        assert!(shuffled_mines.len() == 0);
+        if !shuffled_mines.is_empty() {
+            return Err(SolverError::MineCount.into());
+        }
+
+        Ok(true)
+    }
+
+    /// Samples a layout of the mines for each graph, such that every
+    /// consistent layout of the whole board is equally likely.
+    fn sample_layouts(&self, rng: &mut impl rand::Rng) -> Option<Vec<bv::BitVec>> {
+        let combination = self.mine_distribution().sample(rng)?;
+
+        // Sample uniformly from graph solutions that makes up the combination:
+        izip!(&combination, &self.graphs_solutions)
+            .map(|(mine_count, sol)| sol.alternatives().sample(*mine_count, rng))
+            .collect()
+    }
+
+    /// Asks the backend for a layout of the mines for each graph, satisfying
+    /// the assumptions and leaving a number of mines the unconstrained cells
+    /// can hold.
+    fn solve_layouts(
+        &self,
+        assumptions: &[Vec<(u32, bool)>],
+        rng: &mut impl rand::Rng,
+    ) -> Option<Vec<bv::BitVec>> {
+        // All the graphs are put together, so that the backend
+        // can account for the total number of mines:
+        let mut offsets = Vec::new();
+        let mut topology = search::Topology {
+            unknown_count: 0,
+            clues: Vec::new(),
+        };
+        let mut all_assumptions = Vec::new();
+        for (sol, graph_assumptions) in izip!(&self.graphs_solutions, assumptions) {
+            let offset = topology.unknown_count;
+            offsets.push(offset);
+
+            topology.clues.extend(sol.topology.clues.iter().map(|clue| {
+                search::Clue {
+                    mine_count: clue.mine_count,
+                    adjacency: clue
+                        .adjacency
+                        .iter()
+                        .map(|unknown| unknown + offset)
+                        .collect(),
+                }
+            }));
+            all_assumptions.extend(
+                graph_assumptions
+                    .iter()
+                    .map(|(unknown, is_mine)| (unknown + offset, *is_mine)),
+            );
+            topology.unknown_count += sol.topology.unknown_count;
+        }
+
+        let hidden_mines = self.grid.counters.hidden_mines as usize;
+        let unconstrained = self.grid.counters.unconstrained_cells as usize;
+        let model = self.backend.solve(
+            &topology,
+            &all_assumptions,
+            hidden_mines.saturating_sub(unconstrained)..=hidden_mines,
+            rng,
+            &self.progress.cancelled,
+        )?;
+
+        Some(
+            izip!(&offsets, &self.graphs_solutions)
+                .map(|(offset, sol)| {
+                    let start = *offset as usize;
+                    model[start..start + sol.topology.unknown_count as usize].to_bitvec()
+                })
+                .collect(),
+        )
+    }
 
-        true
+    /// Enumerates the alternatives of the graphs the backend left
+    /// unenumerated, for the queries that need all of them.
+    fn enumerate_alternatives(&mut self) {
+        let progress = &self.progress;
+        self.graphs_solutions.par_iter_mut().for_each(|sol| {
+            if sol.alternatives.is_none() {
+                sol.alternatives = Some(progress.enumerate(&sol.topology));
+            }
+        });
     }
 
+    /// Weights the number of mines of each graph by the number of full
+    /// board layouts it allows, in the order of Bdd::ln_counts_by_mines().
+    fn mine_distribution(&self) -> MineDistribution {
+        // Weight each combination of mine counts by the number of full board
+        // layouts it allows, so that every consistent layout is equally likely:
+        MineDistribution::new(
+            self.graphs_solutions
+                .iter()
+                .map(|sol| sol.alternatives().ln_counts_by_mines().collect())
+                .collect(),
+            self.grid.counters.hidden_mines,
+            self.grid.counters.unconstrained_cells,
+        )
+    }
+
+    /// Finds why the cells in "revealed" can not all be empty.
+    ///
+    /// Must be called after find_acomodating_solution() failed for the same
+    /// cells, and before any new clue is added. The clues in the explanation
+    /// are minimal: removing any of them allows a consistent layout.
+    pub fn explain_loss(&self, revealed: &[Key]) -> Option<LossExplanation> {
+        let mut assumptions: Vec<(Key, bool)> = revealed.iter().map(|key| (*key, false)).collect();
+        let (clues, mine_total) = self.find_contradiction(&assumptions)?;
+
+        // Remove every revealed cell not needed, in case many were revealed:
+        let mut i = 0;
+        while i < assumptions.len() {
+            let removed = assumptions.remove(i);
+            if self.is_consistent(&clues, &assumptions, mine_total) {
+                assumptions.insert(i, removed);
+                i += 1;
+            }
+        }
+
+        Some(LossExplanation {
+            clues,
+            revealed: assumptions.into_iter().map(|(key, _)| key).collect(),
+            uses_mine_count: mine_total.is_some(),
+        })
+    }
+
+    /// Whether there is any layout of the mines consistent with the clues.
+    pub fn is_feasible(&mut self) -> bool {
+        self.ln_layouts() > f64::NEG_INFINITY
+    }
+
+    /// Natural logarithm of the number of consistent layouts of the mines,
+    /// which is negative infinity if there is none.
+    pub fn ln_layouts(&mut self) -> f64 {
+        self.enumerate_alternatives();
+        self.mine_distribution().ln_layouts()
+    }
+
+    /// Computes, for every cell, the probability it holds a mine, considering
+    /// every consistent layout of the mines as equally likely.
+    ///
+    /// Revealed cells have probability 0.
+    pub fn mine_probabilities(&mut self) -> grid::Grid<f64, u16, ()> {
+        self.enumerate_alternatives();
+        let mut probs = grid::Grid::new(self.grid.width(), self.grid.height(), (), 0.0);
+
+        let distribution = self.mine_distribution();
+        let ln_layouts = distribution.ln_layouts();
+
+        for (row, col) in self.grid_keys() {
+            if let CellState::Mine = self.grid.get(row, col) {
+                let Ok(()) = probs.set(row, col, 1.0);
+            }
+        }
+
+        // Each alternative of a graph is weighted by how many full board
+        // layouts it takes part in, which depends only on its number of mines:
+        for (i, graph) in self.graphs_solutions.iter().enumerate() {
+            let alternatives = graph.alternatives();
+
+            let mut alt_probs = Vec::new();
+            for ((mine_count, ln_alts), weight) in izip!(
+                alternatives.ln_counts_by_mines(),
+                distribution.graph_weights(i)
+            ) {
+                alt_probs.resize(mine_count as usize + 1, f64::NEG_INFINITY);
+                alt_probs[mine_count as usize] = weight - ln_layouts - ln_alts;
+            }
+
+            let cell_probs = alternatives.ln_value_weights(&alt_probs);
+            for ((row, col), idx) in graph.tile_map.iter() {
+                let [_, ln_mine_prob] = cell_probs[*idx as usize];
+                let Ok(()) = probs.set(*row, *col, ln_mine_prob.exp());
+            }
+        }
+
+        // Unconstrained cells share equally the mines left over:
+        let unconstrained = self.grid.counters.unconstrained_cells;
+        if unconstrained > 0 {
+            let expected_left: f64 = distribution
+                .left_over_weights()
+                .map(|(left, weight)| (weight - ln_layouts).exp() * f64::from(left))
+                .sum();
+            let prob = expected_left / f64::from(unconstrained);
+
+            for (row, col) in self.grid_keys() {
+                if let CellState::UnknownUnconstrained = self.grid.get(row, col) {
+                    let Ok(()) = probs.set(row, col, prob);
+                }
+            }
+        }
+
+        probs
+    }
+
+    /// Finds a hidden cell whose content is the same in all the consistent
+    /// mine layouts, along with the clues proving it, preferring the empty
+    /// ones. Only the cells "wanted" accepts are considered, and the search
+    /// stops at the first proven, since each proof takes many solves.
+    pub fn find_hint(&mut self, mut wanted: impl FnMut(Key, bool) -> bool) -> Option<Hint> {
+        self.enumerate_alternatives();
+        let mut forced = self.forced_cells();
+        forced.retain(|(cell, is_mine)| wanted(*cell, *is_mine));
+        forced.sort_by_key(|(_, is_mine)| *is_mine);
+
+        forced.into_iter().find_map(|(cell, is_mine)| {
+            let (clues, mine_total) = self.find_contradiction(&[(cell, !is_mine)])?;
+            Some(Hint {
+                cell,
+                is_mine,
+                clues,
+                uses_mine_count: mine_total.is_some(),
+            })
+        })
+    }
+
+    /// Finds every hidden cell whose content is the same in all the
+    /// consistent mine layouts, without looking for a proof.
+    pub fn find_forced_cells(&mut self) -> Vec<(Key, bool)> {
+        self.enumerate_alternatives();
+        let mut forced = self.forced_cells();
+        forced.sort_unstable();
+        forced
+    }
+
+    /// Finds the cells that are either mines in all consistent layouts, or
+    /// empty in all of them.
+    fn forced_cells(&self) -> Vec<(Key, bool)> {
+        let mut forced = Vec::new();
+
+        // Cells already deduced while adding clues:
+        for (row, col) in self.grid_keys() {
+            match self.grid.get(row, col) {
+                CellState::Mine => forced.push(((row, col), true)),
+                CellState::Empty => forced.push(((row, col), false)),
+                _ => (),
+            }
+        }
+
+        // Cells that have the same value in every alternative of a graph,
+        // considering only the alternatives whose number of mines fits
+        // together with the other graphs:
+        let distribution = self.mine_distribution();
+        for (i, graph) in self.graphs_solutions.iter().enumerate() {
+            let alternatives = graph.alternatives();
+
+            let mut feasible = Vec::new();
+            for ((mine_count, _), weight) in izip!(
+                alternatives.ln_counts_by_mines(),
+                distribution.graph_weights(i)
+            ) {
+                if weight > f64::NEG_INFINITY {
+                    feasible.resize(mine_count as usize + 1, f64::NEG_INFINITY);
+                    feasible[mine_count as usize] = 0.0;
+                }
+            }
+
+            let feasible_values = alternatives.ln_value_weights(&feasible);
+            for (key, idx) in graph.tile_map.iter() {
+                let [empty, mine] = feasible_values[*idx as usize];
+                let can_be_mine = mine > f64::NEG_INFINITY;
+                let can_be_empty = empty > f64::NEG_INFINITY;
+                if can_be_mine != can_be_empty {
+                    forced.push((*key, can_be_mine));
+                }
+            }
+        }
+
+        // Unconstrained cells are forced if they must all take the same value:
+        let left_overs: Vec<u32> = distribution
+            .left_over_weights()
+            .map(|(left, _)| left)
+            .collect();
+        let unconstrained = self.grid.counters.unconstrained_cells;
+        let all_mines = left_overs.iter().all(|left| *left == unconstrained);
+        let all_empty = left_overs.iter().all(|left| *left == 0);
+        if !left_overs.is_empty() && (all_mines || all_empty) {
+            for (row, col) in self.grid_keys() {
+                if let CellState::UnknownUnconstrained = self.grid.get(row, col) {
+                    forced.push(((row, col), all_mines));
+                }
+            }
+        }
+
+        forced
+    }
+
+    /// Finds a minimal set of clues ruling out the cells in "assumptions"
+    /// being mines or empty, as given. If the clues alone are not enough,
+    /// the total number of mines is also used, and returned.
+    fn find_contradiction(&self, assumptions: &[(Key, bool)]) -> Option<(Vec<Key>, Option<u32>)> {
+        let cells: Vec<Key> = assumptions.iter().map(|(key, _)| *key).collect();
+
+        // First try with only the clues close to the assumed cells,
+        // which is what the player usually looks at:
+        let nearby = self.clues_connected_to(&cells);
+        let (mut clues, mine_total) = if !self.is_consistent(&nearby, assumptions, None) {
+            (nearby, None)
+        } else {
+            // Then it must be because of the number of mines left:
+            let mine_total = Some(self.mine_total());
+            let all_clues: Vec<Key> = self
+                .grid_keys()
+                .filter(|&(row, col)| {
+                    matches!(self.grid.get(row, col), CellState::Clue(_))
+                        && self
+                            .neighbors_of(row, col)
+                            .any(|(row, col)| self.is_hidden(row, col))
+                })
+                .collect();
+            if self.is_consistent(&all_clues, assumptions, mine_total) {
+                return None;
+            }
+            (all_clues, mine_total)
+        };
+
+        // Remove every clue that is not needed to prove the inconsistency:
+        let mut i = 0;
+        while i < clues.len() {
+            let removed = clues.remove(i);
+            if self.is_consistent(&clues, assumptions, mine_total) {
+                clues.insert(i, removed);
+                i += 1;
+            }
+        }
+
+        Some((clues, mine_total))
+    }
+
+    /// Tells if there is a mine layout satisfying the given clues where the
+    /// cells in "assumptions" are mines or empty, as given. If "mine_total"
+    /// is given, the layout must also have that many mines among the hidden
+    /// cells.
+    fn is_consistent(
+        &self,
+        clues: &[Key],
+        assumptions: &[(Key, bool)],
+        mine_total: Option<u32>,
+    ) -> bool {
+        let mut unk_map = HashMap::<Key, u32>::new();
+        let mut unk_id = |key| {
+            let len = unk_map.len() as u32;
+            *unk_map.entry(key).or_insert(len)
+        };
+
+        let mut search_clues = Vec::new();
+        for &(row, col) in clues {
+            // A hidden cell tells nothing about its neighbors:
+            let mine_count = match self.revealed_clue(row, col) {
+                Some(clue) => clue,
+                None => continue,
+            };
+
+            let mut adjacency = Vec::new();
+            for (row, col) in self.neighbors_of(row, col) {
+                if self.is_hidden(row, col) {
+                    adjacency.push(unk_id((row, col)));
+                }
+            }
+            search_clues.push(search::Clue {
+                mine_count,
+                adjacency,
+            });
+        }
+
+        let assumptions: Vec<(u32, bool)> = assumptions
+            .iter()
+            .map(|(key, is_mine)| (unk_id(*key), *is_mine))
+            .collect();
+
+        let topology = search::Topology {
+            unknown_count: unk_map.len() as u32,
+            clues: search_clues,
+        };
+
+        // Without the total, any number of mines will do. With it, the hidden
+        // cells outside the topology must be able to hold the mines left over.
+        let mine_range = match mine_total {
+            None => 0..=topology.unknown_count as usize,
+            Some(mine_total) => {
+                let free_cells = self
+                    .grid_keys()
+                    .filter(|&(row, col)| self.is_hidden(row, col))
+                    .count()
+                    - topology.unknown_count as usize;
+                (mine_total as usize).saturating_sub(free_cells)..=mine_total as usize
+            }
+        };
+
+        // Only satisfiability matters here, so randomness is not needed:
+        let mut rng = rand::rngs::mock::StepRng::new(0, 1);
+        self.backend
+            .solve(
+                &topology,
+                &assumptions,
+                mine_range,
+                &mut rng,
+                &self.progress.cancelled,
+            )
+            .is_some()
+    }
+
+    /// Finds the clues reachable from the given cells by walking through
+    /// hidden cells and the clues around them.
+    fn clues_connected_to(&self, start: &[Key]) -> Vec<Key> {
+        let mut visited: HashSet<Key> = HashSet::from_iter(start.iter().copied());
+        let mut queue: VecDeque<Key> = start.iter().copied().collect();
+        let mut clues = Vec::new();
+
+        while let Some((row, col)) = queue.pop_front() {
+            let is_clue = matches!(self.grid.get(row, col), CellState::Clue(_));
+            if is_clue {
+                clues.push((row, col));
+            }
+
+            for (row, col) in self.neighbors_of(row, col) {
+                // Clues only lead to hidden cells, and hidden cells only lead to clues:
+                let is_next_clue = matches!(self.grid.get(row, col), CellState::Clue(_));
+                if is_clue != is_next_clue && visited.insert((row, col)) {
+                    queue.push_back((row, col));
+                }
+            }
+        }
+
+        clues
+    }
+
+    /// The value of the clue as displayed to the player, before the
+    /// known mines around it were discounted, or None if the cell is hidden.
+    fn revealed_clue(&self, row: u16, col: u16) -> Option<u8> {
+        let known_mines = self
+            .neighbors_of(row, col)
+            .filter(|&(row, col)| matches!(self.grid.get(row, col), CellState::Mine))
+            .count() as u8;
+
+        match self.grid.get(row, col) {
+            CellState::Clue(val) => Some(val + known_mines),
+            _ => None,
+        }
+    }
+
+    fn is_hidden(&self, row: u16, col: u16) -> bool {
+        !matches!(self.grid.get(row, col), CellState::Clue(_))
+    }
+
+    /// Total number of mines among the hidden cells.
+    fn mine_total(&self) -> u32 {
+        let known_mines = self
+            .grid_keys()
+            .filter(|&(row, col)| matches!(self.grid.get(row, col), CellState::Mine))
+            .count() as u32;
+        self.grid.counters.hidden_mines + known_mines
+    }
+
+    fn grid_keys(&self) -> impl Iterator<Item = Key> {
+        let width = self.grid.width();
+        (0..self.grid.height()).flat_map(move |row| (0..width).map(move |col| (row, col)))
+    }
+
+    /// Checks what the solver relies on: the counters match the cells,
+    /// every clue can still be satisfied and has already propagated what
+    /// it forces, and the cells known to be mines or empty agree with the
+    /// real layout, where "is_mine" tells whether a cell holds a mine.
+    pub fn check_invariants(&self, is_mine: impl Fn(u16, u16) -> bool) -> Result<(), SolverError> {
+        let mut unconstrained_cells = 0u32;
+        let mut hidden_mines = 0u32;
+        for (row, col) in self.grid_keys() {
+            let unknowns = self
+                .neighbors_of(row, col)
+                .filter(|&(row, col)| {
+                    matches!(self.grid.get(row, col), CellState::UnknownConstrained)
+                })
+                .count();
+            let next_to_clue = self.neighbors_of(row, col).any(
+                |(row, col)| matches!(self.grid.get(row, col), CellState::Clue(val) if *val > 0),
+            );
+
+            let is_consistent = match *self.grid.get(row, col) {
+                CellState::UnknownUnconstrained => {
+                    unconstrained_cells += 1;
+                    hidden_mines += is_mine(row, col) as u32;
+                    !next_to_clue
+                }
+                CellState::UnknownConstrained => {
+                    hidden_mines += is_mine(row, col) as u32;
+                    next_to_clue
+                }
+                CellState::Mine => is_mine(row, col),
+                CellState::Empty => !is_mine(row, col),
+                CellState::Clue(val) => {
+                    let is_unconstrained = |(row, col): Key| {
+                        matches!(self.grid.get(row, col), CellState::UnknownUnconstrained)
+                    };
+                    // A clue with all its unknowns being mines, or none,
+                    // would have already set them:
+                    let is_propagated = match val {
+                        0 => unknowns == 0,
+                        val => (val as usize) < unknowns,
+                    };
+                    !is_mine(row, col)
+                        && !self.neighbors_of(row, col).any(is_unconstrained)
+                        && is_propagated
+                }
+            };
+            if !is_consistent {
+                return Err(SolverError::Inconsistent((row, col)));
+            }
+        }
+
+        if unconstrained_cells != self.grid.counters.unconstrained_cells
+            || hidden_mines != self.grid.counters.hidden_mines
+        {
+            return Err(SolverError::Counters);
+        }
+        Ok(())
+    }
+
+    #[allow(dead_code)]
     pub fn print(&self) {
         let mut map = HashMap::new();
         for (i, gs) in self.graphs_solutions.iter().enumerate() {
@@ -528,18 +1395,18 @@ impl PartialSolution {
             }
         }
 
-        print!("\n");
+        println!();
         for (i, row) in self.grid.rows().enumerate() {
             for (j, cell) in row.iter().enumerate() {
                 if let CellState::UnknownConstrained = cell {
-                    print!("\u{20dd}{}", map.get(&(i as u8, j as u8)).unwrap());
+                    print!("\u{20dd}{}", map.get(&(i as u16, j as u16)).unwrap());
                 } else {
                     print!("{}", cell);
                 }
             }
-            print!("\n");
+            println!();
         }
-        print!("\n");
+        println!();
 
         //    for (i, gs) in self.graphs_solutions.iter().enumerate() {
         //        println!("{}:", i);
@@ -547,7 +1414,7 @@ impl PartialSolution {
         //            println!("  {}", s);
         //        }
         //    }
-        //    print!("\n");
+        //    println!();
     }
 }
 
@@ -568,10 +1435,280 @@ impl std::fmt::Display for CellState {
 }
 
 impl NeighborIterable for PartialSolution {
-    fn width(&self) -> u8 {
+    fn width(&self) -> u16 {
         self.grid.width()
     }
-    fn height(&self) -> u8 {
+    fn height(&self) -> u16 {
         self.grid.height()
     }
+    fn topology(&self) -> &dyn Topology {
+        &*self.topology
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend;
+    use crate::minefield::{GameError, Minefield};
+    use crate::topology;
+    use crate::verify::Layouts;
+    use rand::{Rng, SeedableRng};
+
+    fn solve(board: &str) -> PartialSolution {
+        let position: Position = board.parse().unwrap();
+        PartialSolution::from_position(&position, Arc::new(backend::Diagram)).unwrap()
+    }
+
+    /// The 1-2-1 pattern: the two outer cells are mines, and the middle one
+    /// is safe.
+    const ONE_TWO_ONE: &str = "mines: 2\n...\n121\n";
+
+    #[test]
+    fn hints_the_first_cell_wanted() {
+        let mut sol = solve(ONE_TWO_ONE);
+
+        let hint = sol.find_hint(|_, _| true).unwrap();
+        assert_eq!((hint.cell, hint.is_mine), ((0, 1), false));
+        assert!(!hint.clues.is_empty());
+        assert!(hint.clues.iter().all(|(row, _)| *row == 1));
+
+        let hint = sol.find_hint(|cell, _| cell != (0, 1)).unwrap();
+        assert!(hint.is_mine);
+        assert!([(0, 0), (0, 2)].contains(&hint.cell));
+
+        assert!(sol.find_hint(|_, _| false).is_none());
+    }
+
+    /// The solver with the clues of the board added, but not yet solved.
+    fn with_clues(board: &str) -> PartialSolution {
+        let position: Position = board.parse().unwrap();
+        let cells = &position.cells;
+        let mut sol = PartialSolution::new(
+            cells.width(),
+            cells.height(),
+            position.mine_count,
+            position.topology.clone(),
+            Arc::new(backend::Diagram),
+        );
+        for (key, clue) in position.clues() {
+            sol.add_clue(key, clue).unwrap();
+        }
+        sol
+    }
+
+    /// Draws what the solver knows of each cell: "*" for a mine, "o" for an
+    /// empty cell, "." for an unknown one and "#" for a clue.
+    fn draw(sol: &PartialSolution) -> Vec<String> {
+        sol.grid
+            .rows()
+            .map(|cells| {
+                cells
+                    .iter()
+                    .map(|cell| match cell {
+                        CellState::Mine => '*',
+                        CellState::Empty => 'o',
+                        CellState::Clue(_) => '#',
+                        _ => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Checks no single clue shows anything about the first row, but the
+    /// pairs of clues show what "expected" draws.
+    fn check_pattern(board: &str, expected: &[&str]) {
+        let mut sol = with_clues(board);
+        let unknown = draw(&sol);
+        assert!(unknown[0].chars().all(|cell| cell == '.'), "{:?}", unknown);
+        sol.pattern_update().unwrap();
+        assert_eq!(draw(&sol), expected);
+    }
+
+    #[test]
+    fn deduces_edge_patterns() {
+        check_pattern(ONE_TWO_ONE, &["*o*", "###"]);
+        check_pattern("mines: 2\n....\n1221\n", &["o**o", "####"]);
+        // The 1-2-1 doesn't need the corners:
+        check_pattern("mines: 2\n.....\n.121.\n", &["o*o*o", "o###o"]);
+        // The 1-1 pattern, from each corner:
+        check_pattern("mines: 2\n....\n1111\n", &["*oo*", "####"]);
+        check_pattern("mines: 1\n...\n11.\n", &["..o", "##o"]);
+    }
+
+    #[test]
+    fn deduces_nothing_where_the_patterns_dont_hold() {
+        // The cells beside the clues may hold the mines, so the pairs of
+        // clues show nothing:
+        check_pattern("mines: 2\n....\n.11.\n", &["....", ".##."]);
+        // On hexagons, the second 1 doesn't see the first cell, so the
+        // 1-1 doesn't clear the third one as it does on squares:
+        check_pattern("mines: 1\ntopology: hexagonal\n...\n11.\n", &["...", "##."]);
+    }
+
+    #[test]
+    fn reports_impossible_clues() {
+        let mut sol = solve("mines: 1\n..\n1.\n");
+        assert!(matches!(
+            sol.add_clue((1, 0), 1),
+            Err(SolverError::AlreadyRevealed((1, 0)))
+        ));
+        // Only (1, 1) is left for the mine of the clue:
+        sol.add_clue((0, 0), 1).unwrap();
+        sol.add_clue((0, 1), 1).unwrap();
+        assert!(matches!(
+            sol.add_clue((1, 1), 1),
+            Err(SolverError::ClueOnMine((1, 1)))
+        ));
+
+        let mut sol = solve("mines: 1\n...\n");
+        assert!(matches!(
+            sol.add_clue((0, 0), 2),
+            Err(SolverError::Inconsistent((0, 0)))
+        ));
+
+        // Two mines around the clue, but only one on the board:
+        let mut sol = solve("mines: 1\n...\n");
+        assert!(matches!(
+            sol.add_clue((0, 1), 2),
+            Err(SolverError::MineCount)
+        ));
+
+        // The clue leaves no room for the mine:
+        let position = "mines: 1\n.\n0\n".parse().unwrap();
+        let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(0);
+        assert!(matches!(
+            Minefield::from_position(&position, Arc::new(backend::Diagram), &mut rng),
+            Err(GameError::Solver(SolverError::MineCount))
+        ));
+    }
+
+    #[test]
+    fn reports_corrupt_counters() {
+        let is_mine = |row, col| row == 0 && col != 1;
+        let sol = solve(ONE_TWO_ONE);
+        sol.check_invariants(is_mine).unwrap();
+        let mut saved = bincode::serialize(&sol).unwrap();
+        // The grid is saved first, and its counters first in it, starting
+        // with the number of unconstrained cells:
+        saved[0] += 1;
+        let loaded: PartialSolution = bincode::deserialize(&saved).unwrap();
+        assert!(matches!(
+            loaded.check_invariants(is_mine),
+            Err(SolverError::Counters)
+        ));
+    }
+
+    #[test]
+    fn refuses_to_clear_mines() {
+        // add_clue() checks for mines before clearing anything, so only the
+        // counters themselves can show this:
+        use grid::GridCounters;
+        let mut counters = Counters {
+            unconstrained_cells: 0,
+            hidden_mines: 0,
+        };
+        assert!(matches!(
+            counters.notify_change(&CellState::Mine, &CellState::Empty),
+            Err(SolverError::InvalidChange)
+        ));
+    }
+
+    /// Checks the explanation proves the loss, and that it doesn't anymore
+    /// without any one of its clues or revealed cells.
+    fn check_minimal(sol: &PartialSolution, explanation: &LossExplanation) {
+        let mine_total = explanation.uses_mine_count.then(|| sol.mine_total());
+        let assumptions: Vec<(Key, bool)> = explanation
+            .revealed
+            .iter()
+            .map(|key| (*key, false))
+            .collect();
+        assert!(!sol.is_consistent(&explanation.clues, &assumptions, mine_total));
+
+        for i in 0..explanation.clues.len() {
+            let mut clues = explanation.clues.clone();
+            clues.remove(i);
+            assert!(sol.is_consistent(&clues, &assumptions, mine_total));
+        }
+        for i in 0..assumptions.len() {
+            let mut assumptions = assumptions.clone();
+            assumptions.remove(i);
+            assert!(sol.is_consistent(&explanation.clues, &assumptions, mine_total));
+        }
+    }
+
+    #[test]
+    fn explains_the_loss_with_minimal_clues() {
+        // With (0, 0) empty, (1, 2) leaves only one mine for (1, 1), and
+        // the clue (1, 0) is not needed to see it:
+        let sol = solve(ONE_TWO_ONE);
+        let mut explanation = sol.explain_loss(&[(0, 0)]).unwrap();
+        check_minimal(&sol, &explanation);
+        explanation.clues.sort_unstable();
+        assert_eq!(explanation.clues, [(1, 1), (1, 2)]);
+        assert_eq!(explanation.revealed, [(0, 0)]);
+        assert!(!explanation.uses_mine_count);
+
+        // Revealing two of the cells of (1, 1) is enough:
+        let explanation = sol.explain_loss(&[(0, 1), (0, 0)]).unwrap();
+        check_minimal(&sol, &explanation);
+        assert_eq!(explanation.clues, [(1, 1)]);
+        assert_eq!(explanation.revealed, [(0, 1), (0, 0)]);
+
+        // Only the number of mines proves there is no safe cell:
+        let sol = solve("mines: 2\n..\n");
+        let explanation = sol.explain_loss(&[(0, 1)]).unwrap();
+        check_minimal(&sol, &explanation);
+        assert!(explanation.clues.is_empty());
+        assert!(explanation.uses_mine_count);
+
+        // Not a loss at all:
+        assert!(sol.explain_loss(&[]).is_none());
+        assert!(solve(ONE_TWO_ONE).explain_loss(&[(0, 1)]).is_none());
+    }
+
+    #[test]
+    fn probabilities_match_the_layout_counts() {
+        for name in ["diagram", "enumerator", "sat"] {
+            for seed in 0..20 {
+                let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(seed);
+                let mine_count = rng.gen_range(2..=8);
+                let mut minefield = Minefield::create_random(
+                    5,
+                    4,
+                    mine_count,
+                    Arc::new(topology::Square),
+                    backend::by_name(name).unwrap(),
+                    &mut rng,
+                )
+                .unwrap();
+
+                // Random clicks, until one of them loses. With 20 tiles,
+                // the layouts can always be enumerated:
+                for _ in 0..4 {
+                    let (row, col) = (rng.gen_range(0..4), rng.gen_range(0..5));
+                    if !minefield.reveal(&mut rng, row, col).unwrap() {
+                        break;
+                    }
+                    let layouts = Layouts::enumerate(&minefield, &[]).unwrap();
+                    let probabilities = minefield.mine_probabilities();
+                    for (&(row, col), &mines) in &layouts.mines {
+                        let expected = mines as f64 / layouts.count as f64;
+                        let found = *probabilities.get(row, col);
+                        assert!(
+                            (found - expected).abs() < 1e-9,
+                            "{} backend, seed {}: ({}, {}) has probability {} instead of {}",
+                            name,
+                            seed,
+                            row,
+                            col,
+                            found,
+                            expected
+                        );
+                    }
+                }
+            }
+        }
+    }
 }
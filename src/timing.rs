@@ -0,0 +1,38 @@
+//! How long the slow steps of a move take, shown on the standard error.
+//!
+//! In the browser, the time is measured with the clock of the page, and
+//! shown on the console of its developer tools.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use web_time::Instant;
+
+/// Whether the times are shown, which they are unless turned off.
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Turns on or off showing the times, as needed by the programs whose
+/// terminal they would get mixed in.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Calls "f", and shows how long it took, labeled with "what".
+pub fn measure<T>(what: &str, f: impl FnOnce() -> T) -> T {
+    let begin = Instant::now();
+    let ret = f();
+    if ENABLED.load(Ordering::Relaxed) {
+        show(what, begin.elapsed());
+    }
+    ret
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn show(what: &str, elapsed: Duration) {
+    eprintln!("{}: {:0.06}", what, elapsed.as_secs_f64());
+}
+
+#[cfg(target_arch = "wasm32")]
+fn show(what: &str, elapsed: Duration) {
+    let line = format!("{}: {:0.06}", what, elapsed.as_secs_f64());
+    web_sys::console::log_1(&line.into());
+}
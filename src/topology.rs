@@ -0,0 +1,457 @@
+//! The shapes of the tiles, and which of them are neighbors.
+//!
+//! Whatever the shape of its tiles, a board is stored in rows and columns,
+//! like in Grid: the topology tells which of them are neighbors, the tiles
+//! their clues count the mines of, and where each one is drawn. Most often
+//! the neighbors are the tiles touching each other, but a Neighborhood can
+//! be any other set of tiles around a square tile.
+
+use super::neighbor_iter::NeighborIter;
+use std::fmt;
+use std::sync::Arc;
+
+pub trait Topology: Send + Sync {
+    /// The neighbors of the tile at "row" and "col", on a board of the given
+    /// size. The solver relies on the relation going both ways: a tile is
+    /// among the neighbors of each of its neighbors.
+    fn neighbors_of(&self, width: u16, height: u16, row: u16, col: u16) -> NeighborIter;
+
+    /// The corners of the tile at "row" and "col", in order around it, in a
+    /// plane where the tiles are about one unit wide, growing from the
+    /// origin to the right and down.
+    fn outline(&self, row: u16, col: u16) -> Vec<(f32, f32)>;
+
+    /// The name the topology is found by in by_name().
+    fn name(&self) -> &'static str;
+}
+
+/// Finds the topology with the given name, either "square", "hexagonal",
+/// "triangular", "toroidal", or one of the neighborhoods, "orthogonal",
+/// "knight" or "radius-2".
+pub fn by_name(name: &str) -> Option<Arc<dyn Topology>> {
+    all().into_iter().find(|topology| topology.name() == name)
+}
+
+/// Every topology, in the order they are offered to the player: the shapes
+/// of the tiles first, then the neighborhoods.
+pub fn all() -> Vec<Arc<dyn Topology>> {
+    vec![
+        Arc::new(Square),
+        Arc::new(Hexagonal),
+        Arc::new(Triangular),
+        Arc::new(Toroidal),
+        Arc::new(Neighborhood::ORTHOGONAL),
+        Arc::new(Neighborhood::KNIGHT),
+        Arc::new(Neighborhood::RADIUS_2),
+    ]
+}
+
+/// Saves a topology by its name, with serde's "with" attribute.
+pub(crate) mod serde_by_name {
+    use super::Topology;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::sync::Arc;
+
+    pub fn serialize<S: Serializer>(topology: &Arc<dyn Topology>, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(topology.name())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Arc<dyn Topology>, D::Error> {
+        let name = String::deserialize(d)?;
+        super::by_name(&name)
+            .ok_or_else(|| D::Error::custom(format!("unknown topology \"{}\"", name)))
+    }
+}
+
+/// The 8 tiles around a square tile.
+#[rustfmt::skip]
+const SQUARE_DELTAS: [(i16, i16); 8] = [
+    (-1, -1), (-1, 0), (-1, 1),
+    ( 0, -1),          ( 0, 1),
+    ( 1, -1), ( 1, 0), ( 1, 1),
+];
+
+fn square_outline(row: u16, col: u16) -> Vec<(f32, f32)> {
+    let (x, y) = (f32::from(col), f32::from(row));
+    vec![(x, y), (x + 1.0, y), (x + 1.0, y + 1.0), (x, y + 1.0)]
+}
+
+/// The classic board, where the tiles touching a tile's sides or corners
+/// are its neighbors.
+pub struct Square;
+
+impl Topology for Square {
+    fn neighbors_of(&self, width: u16, height: u16, row: u16, col: u16) -> NeighborIter {
+        NeighborIter::from_deltas(width, height, row, col, &SQUARE_DELTAS, false)
+    }
+
+    fn outline(&self, row: u16, col: u16) -> Vec<(f32, f32)> {
+        square_outline(row, col)
+    }
+
+    fn name(&self) -> &'static str {
+        "square"
+    }
+}
+
+/// The square board where the edges wrap around, so that every tile has 8
+/// neighbors: the ones on the first row are next to the ones on the last,
+/// and likewise for the columns.
+pub struct Toroidal;
+
+impl Topology for Toroidal {
+    fn neighbors_of(&self, width: u16, height: u16, row: u16, col: u16) -> NeighborIter {
+        NeighborIter::from_deltas(width, height, row, col, &SQUARE_DELTAS, true)
+    }
+
+    fn outline(&self, row: u16, col: u16) -> Vec<(f32, f32)> {
+        square_outline(row, col)
+    }
+
+    fn name(&self) -> &'static str {
+        "toroidal"
+    }
+}
+
+/// Square tiles whose neighbors are the tiles at some offsets, for the
+/// variants of the game changing what a clue counts. The offsets are from a
+/// square tile on a board with edges: they don't apply to hexagons or
+/// triangles, whose offsets change from a tile to the next, nor wrap around
+/// like the toroidal board.
+pub struct Neighborhood {
+    name: &'static str,
+    deltas: &'static [(i16, i16)],
+}
+
+impl Neighborhood {
+    /// Only the 4 tiles sharing a side.
+    pub const ORTHOGONAL: Self = Self {
+        name: "orthogonal",
+        deltas: &[(-1, 0), (0, -1), (0, 1), (1, 0)],
+    };
+
+    /// The 8 tiles a knight of chess can move to.
+    #[rustfmt::skip]
+    pub const KNIGHT: Self = Self {
+        name: "knight",
+        deltas: &[
+            (-2, -1), (-2, 1), (-1, -2), (-1, 2),
+            (1, -2), (1, 2), (2, -1), (2, 1),
+        ],
+    };
+
+    /// The 24 other tiles of the 5x5 block around the tile.
+    #[rustfmt::skip]
+    pub const RADIUS_2: Self = Self {
+        name: "radius-2",
+        deltas: &[
+            (-2, -2), (-2, -1), (-2, 0), (-2, 1), (-2, 2),
+            (-1, -2), (-1, -1), (-1, 0), (-1, 1), (-1, 2),
+            ( 0, -2), ( 0, -1),          ( 0, 1), ( 0, 2),
+            ( 1, -2), ( 1, -1), ( 1, 0), ( 1, 1), ( 1, 2),
+            ( 2, -2), ( 2, -1), ( 2, 0), ( 2, 1), ( 2, 2),
+        ],
+    };
+
+    /// A neighborhood of other offsets, which by_name() won't find, so games
+    /// using it can't be saved or recorded. Any number of offsets goes, as
+    /// long as a clue can count them all, and they go both ways, so that
+    /// each tile is among the neighbors of its neighbors.
+    pub fn new(
+        name: &'static str,
+        deltas: &'static [(i16, i16)],
+    ) -> Result<Self, NeighborhoodError> {
+        if deltas.len() > usize::from(u8::MAX) {
+            return Err(NeighborhoodError::TooMany);
+        }
+        for (i, &(dr, dc)) in deltas.iter().enumerate() {
+            if (dr, dc) == (0, 0) {
+                return Err(NeighborhoodError::Itself);
+            }
+            if deltas[..i].contains(&(dr, dc)) {
+                return Err(NeighborhoodError::Repeated(dr, dc));
+            }
+            if !deltas.contains(&(-dr, -dc)) {
+                return Err(NeighborhoodError::OneWay(dr, dc));
+            }
+        }
+        Ok(Self { name, deltas })
+    }
+}
+
+/// Why a set of offsets can't be a Neighborhood.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NeighborhoodError {
+    /// More neighbors than a clue can count.
+    TooMany,
+    /// The offset (0, 0), a tile can't be its own neighbor.
+    Itself,
+    /// The offset is given twice.
+    Repeated(i16, i16),
+    /// The offset is given, but not the opposite one.
+    OneWay(i16, i16),
+}
+
+impl fmt::Display for NeighborhoodError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NeighborhoodError::TooMany => write!(f, "at most {} neighbors", u8::MAX),
+            NeighborhoodError::Itself => write!(f, "a tile can't be its own neighbor"),
+            NeighborhoodError::Repeated(dr, dc) => write!(f, "offset ({}, {}) repeated", dr, dc),
+            NeighborhoodError::OneWay(dr, dc) => {
+                write!(
+                    f,
+                    "offset ({}, {}) without the opposite ({}, {})",
+                    dr, dc, -dr, -dc
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for NeighborhoodError {}
+
+impl Topology for Neighborhood {
+    fn neighbors_of(&self, width: u16, height: u16, row: u16, col: u16) -> NeighborIter {
+        NeighborIter::from_deltas(width, height, row, col, self.deltas, false)
+    }
+
+    fn outline(&self, row: u16, col: u16) -> Vec<(f32, f32)> {
+        square_outline(row, col)
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Hexagons with a corner on top, in rows where the odd ones are shifted
+/// half a tile to the right, so that each tile has 6 neighbors.
+pub struct Hexagonal;
+
+impl Topology for Hexagonal {
+    fn neighbors_of(&self, width: u16, height: u16, row: u16, col: u16) -> NeighborIter {
+        const EVEN_ROW: [(i16, i16); 6] = [(-1, -1), (-1, 0), (0, -1), (0, 1), (1, -1), (1, 0)];
+        const ODD_ROW: [(i16, i16); 6] = [(-1, 0), (-1, 1), (0, -1), (0, 1), (1, 0), (1, 1)];
+
+        let deltas = if row.is_multiple_of(2) {
+            &EVEN_ROW
+        } else {
+            &ODD_ROW
+        };
+        NeighborIter::from_deltas(width, height, row, col, deltas, false)
+    }
+
+    fn outline(&self, row: u16, col: u16) -> Vec<(f32, f32)> {
+        // One unit wide, so the rows are 3/4 of the height apart:
+        let radius = 1.0 / 3f32.sqrt();
+        let x = f32::from(col) + if row.is_multiple_of(2) { 0.5 } else { 1.0 };
+        let y = radius * (1.0 + 1.5 * f32::from(row));
+
+        (0..6)
+            .map(|i| {
+                let angle = std::f32::consts::FRAC_PI_3 * (i as f32 - 1.5);
+                (x + radius * angle.cos(), y + radius * angle.sin())
+            })
+            .collect()
+    }
+
+    fn name(&self) -> &'static str {
+        "hexagonal"
+    }
+}
+
+/// Triangles pointing up and down in turns, the first one of the board
+/// pointing up, where every tile sharing a corner with a tile is its
+/// neighbor, 12 of them.
+pub struct Triangular;
+
+impl Triangular {
+    fn points_up(row: u16, col: u16) -> bool {
+        row.is_multiple_of(2) == col.is_multiple_of(2)
+    }
+}
+
+impl Topology for Triangular {
+    fn neighbors_of(&self, width: u16, height: u16, row: u16, col: u16) -> NeighborIter {
+        // 3 tiles on the side of the tip, 4 beside, 5 on the side of the base:
+        #[rustfmt::skip]
+        const UP: [(i16, i16); 12] = [
+            (-1, -1), (-1, 0), (-1, 1),
+            (0, -2), (0, -1), (0, 1), (0, 2),
+            (1, -2), (1, -1), (1, 0), (1, 1), (1, 2),
+        ];
+        #[rustfmt::skip]
+        const DOWN: [(i16, i16); 12] = [
+            (-1, -2), (-1, -1), (-1, 0), (-1, 1), (-1, 2),
+            (0, -2), (0, -1), (0, 1), (0, 2),
+            (1, -1), (1, 0), (1, 1),
+        ];
+
+        let deltas = if Self::points_up(row, col) {
+            &UP
+        } else {
+            &DOWN
+        };
+        NeighborIter::from_deltas(width, height, row, col, deltas, false)
+    }
+
+    fn outline(&self, row: u16, col: u16) -> Vec<(f32, f32)> {
+        // One unit a side, overlapping the tiles beside by half of it:
+        let height = 3f32.sqrt() / 2.0;
+        let (x, top) = (0.5 * f32::from(col), height * f32::from(row));
+        let bottom = top + height;
+
+        if Self::points_up(row, col) {
+            vec![(x + 0.5, top), (x + 1.0, bottom), (x, bottom)]
+        } else {
+            vec![(x, top), (x + 1.0, top), (x + 0.5, bottom)]
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "triangular"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend;
+    use crate::grid;
+    use crate::minefield::{Minefield, UserMarking};
+    use crate::notation::{Cell, Position};
+    use rand_core::SeedableRng;
+
+    /// Whether the outlines of the two tiles have a corner in common.
+    fn share_corner(topology: &dyn Topology, a: (u16, u16), b: (u16, u16)) -> bool {
+        let corners = topology.outline(b.0, b.1);
+        topology.outline(a.0, a.1).iter().any(|(x, y)| {
+            corners
+                .iter()
+                .any(|(u, v)| (x - u).abs() < 1e-4 && (y - v).abs() < 1e-4)
+        })
+    }
+
+    #[test]
+    fn neighbors_are_the_tiles_touching() {
+        // Wrapping around can't be seen in the outlines:
+        for topology in [&Square as &dyn Topology, &Hexagonal, &Triangular] {
+            let (width, height) = (7, 6);
+            for row in 0..height {
+                for col in 0..width {
+                    let mut neighbors: Vec<_> =
+                        topology.neighbors_of(width, height, row, col).collect();
+                    neighbors.sort_unstable();
+
+                    let mut touching = Vec::new();
+                    for other_row in 0..height {
+                        for other_col in 0..width {
+                            let other = (other_row, other_col);
+                            if other != (row, col) && share_corner(topology, (row, col), other) {
+                                touching.push(other);
+                            }
+                        }
+                    }
+                    assert_eq!(
+                        neighbors,
+                        touching,
+                        "{} at {:?}",
+                        topology.name(),
+                        (row, col)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn neighbors_go_both_ways() {
+        for topology in all() {
+            let (width, height) = (7, 6);
+            for row in 0..height {
+                for col in 0..width {
+                    for (other_row, other_col) in topology.neighbors_of(width, height, row, col) {
+                        assert!(
+                            topology
+                                .neighbors_of(width, height, other_row, other_col)
+                                .any(|neighbor| neighbor == (row, col)),
+                            "{} at {:?}",
+                            topology.name(),
+                            (row, col)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn neighborhoods_count_other_tiles() {
+        let neighbors: Vec<_> = Neighborhood::KNIGHT.neighbors_of(8, 8, 0, 0).collect();
+        assert_eq!(neighbors, [(1, 2), (2, 1)]);
+        assert_eq!(Neighborhood::ORTHOGONAL.neighbors_of(8, 8, 4, 4).count(), 4);
+        assert_eq!(Neighborhood::RADIUS_2.neighbors_of(8, 8, 4, 4).count(), 24);
+    }
+
+    #[test]
+    fn neighborhoods_can_be_any_size() {
+        // The 48 others of the 7x7 block, more than are kept without
+        // allocating:
+        let deltas: Vec<(i16, i16)> = (-3..=3)
+            .flat_map(|dr| (-3..=3).map(move |dc| (dr, dc)))
+            .filter(|&delta| delta != (0, 0))
+            .collect();
+        let radius_3 = Arc::new(Neighborhood::new("radius-3", deltas.leak()).unwrap());
+        assert_eq!(radius_3.neighbors_of(12, 12, 6, 6).count(), 48);
+
+        // A clue too large for a single digit of the notation:
+        let mut cells = vec![Cell::Hidden(UserMarking::None); 49];
+        cells[24] = Cell::Revealed(48);
+        let position = Position {
+            mine_count: 48,
+            topology: radius_3.clone(),
+            cells: grid::Grid::from_vec(7, 7, (), cells).unwrap(),
+        };
+        let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(3);
+        let minefield =
+            Minefield::from_position(&position, Arc::new(backend::Diagram), &mut rng).unwrap();
+        assert!(minefield.position().to_string().contains("...(48)..."));
+
+        let mut minefield =
+            Minefield::create_random(12, 12, 20, radius_3, Arc::new(backend::Diagram), &mut rng)
+                .unwrap();
+        assert!(minefield.reveal(&mut rng, 6, 6).unwrap());
+        minefield.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn rejects_wrong_neighborhoods() {
+        let error = |deltas: &'static [(i16, i16)]| Neighborhood::new("wrong", deltas).err();
+        assert_eq!(error(&[(0, 1), (0, -1)]), None);
+        assert_eq!(error(&[(0, 0)]), Some(NeighborhoodError::Itself));
+        assert_eq!(
+            error(&[(0, 1), (0, -1), (0, 1)]),
+            Some(NeighborhoodError::Repeated(0, 1))
+        );
+        assert_eq!(
+            error(&[(0, 1), (1, 0), (0, -1)]),
+            Some(NeighborhoodError::OneWay(1, 0))
+        );
+
+        let many: Vec<(i16, i16)> = (1..=128).flat_map(|dc| [(0, dc), (0, -dc)]).collect();
+        assert_eq!(error(many.leak()), Some(NeighborhoodError::TooMany));
+    }
+
+    #[test]
+    fn toroidal_wraps_around() {
+        let neighbors: Vec<_> = Toroidal.neighbors_of(5, 4, 0, 0).collect();
+        assert_eq!(neighbors.len(), 8);
+        assert!(neighbors.contains(&(3, 4)));
+
+        // Each tile only once, and never the tile itself, on tiny boards:
+        let neighbors: Vec<_> = Toroidal.neighbors_of(2, 1, 0, 0).collect();
+        assert_eq!(neighbors, [(0, 1)]);
+    }
+}
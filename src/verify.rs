@@ -0,0 +1,268 @@
+//! Brute-force cross-check of the solver, for small boards.
+//!
+//! Every layout of the mines consistent with the revealed tiles is listed,
+//! without any of the solver's cleverness, and compared to what the solver
+//! says. Enabled by setting the environment variable `MINESWAPPER_VERIFY`.
+
+use super::minefield::{Content, Minefield, Tile};
+use super::neighbor_iter::NeighborIterable;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+type Key = (u16, u16);
+
+/// Boards with more hidden tiles than this are not checked, because
+/// listing their layouts would take too long.
+pub const MAX_HIDDEN: usize = 20;
+
+/// Whether the cross-check is enabled by the environment.
+pub fn is_enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| std::env::var_os("MINESWAPPER_VERIFY").is_some())
+}
+
+/// How many consistent layouts there are, and in how many of them each
+/// hidden tile is a mine.
+pub struct Layouts {
+    pub count: u64,
+    pub mines: BTreeMap<Key, u64>,
+}
+
+impl Layouts {
+    /// Lists every layout of the mines among the hidden tiles consistent
+    /// with the revealed ones, where every tile in "empty" is free of mines.
+    /// Returns None if there are more than MAX_HIDDEN hidden tiles.
+    pub fn enumerate(minefield: &Minefield, empty: &[Key]) -> Option<Self> {
+        let mut hidden = Vec::new();
+        let mut clues = Vec::new();
+        for (row, tiles) in (0u16..).zip(minefield.grid.rows()) {
+            for (col, tile) in (0u16..).zip(tiles.iter()) {
+                match tile {
+                    Tile::Hidden(..) => hidden.push((row, col)),
+                    Tile::Revealed(clue) => clues.push(((row, col), *clue)),
+                }
+            }
+        }
+        if hidden.len() > MAX_HIDDEN {
+            return None;
+        }
+
+        let mut search = Search {
+            is_mine: vec![false; hidden.len()],
+            can_be_mine: hidden.iter().map(|key| !empty.contains(key)).collect(),
+            clues_of: vec![Vec::new(); hidden.len()],
+            clues: Vec::new(),
+            mines_left: minefield.mine_count as usize,
+            layouts: Layouts {
+                count: 0,
+                mines: hidden.iter().map(|key| (*key, 0)).collect(),
+            },
+            hidden,
+        };
+        for ((row, col), clue) in clues {
+            let idx = search.clues.len();
+            let mut unassigned = 0;
+            for neighbor in minefield.neighbors_of(row, col) {
+                if let Some(pos) = search.hidden.iter().position(|key| *key == neighbor) {
+                    search.clues_of[pos].push(idx);
+                    unassigned += 1;
+                }
+            }
+            search.clues.push(ClueState {
+                mines_left: clue as usize,
+                unassigned,
+            });
+        }
+
+        if search.clues.iter().all(ClueState::is_possible) {
+            search.assign(0);
+        }
+        Some(search.layouts)
+    }
+
+    /// The hidden tiles that are mines in all the layouts, or empty in all of
+    /// them. Nothing is forced if there is no layout at all.
+    pub fn forced(&self) -> BTreeMap<Key, bool> {
+        if self.count == 0 {
+            return BTreeMap::new();
+        }
+        self.mines
+            .iter()
+            .filter(|(_, mines)| **mines == 0 || **mines == self.count)
+            .map(|(key, mines)| (*key, *mines == self.count))
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ClueState {
+    mines_left: usize,
+    unassigned: usize,
+}
+
+impl ClueState {
+    fn is_possible(&self) -> bool {
+        self.mines_left <= self.unassigned
+    }
+}
+
+struct Search {
+    hidden: Vec<Key>,
+    is_mine: Vec<bool>,
+    can_be_mine: Vec<bool>,
+    /// The clues around each hidden tile.
+    clues_of: Vec<Vec<usize>>,
+    clues: Vec<ClueState>,
+    mines_left: usize,
+    layouts: Layouts,
+}
+
+impl Search {
+    /// Tries both values for the hidden tile at "pos", then goes on to the
+    /// next, backtracking as soon as a clue can't be satisfied.
+    fn assign(&mut self, pos: usize) {
+        if pos == self.hidden.len() {
+            if self.mines_left == 0 {
+                self.layouts.count += 1;
+                for (key, is_mine) in self.hidden.iter().zip(&self.is_mine) {
+                    if *is_mine {
+                        *self.layouts.mines.get_mut(key).unwrap() += 1;
+                    }
+                }
+            }
+            return;
+        }
+        if self.mines_left > self.hidden.len() - pos {
+            return;
+        }
+
+        for is_mine in [false, true] {
+            if is_mine && (self.mines_left == 0 || !self.can_be_mine[pos]) {
+                continue;
+            }
+
+            let saved: Vec<ClueState> = self.clues_of[pos]
+                .iter()
+                .map(|idx| self.clues[*idx])
+                .collect();
+            let mut possible = true;
+            for idx in self.clues_of[pos].iter() {
+                let clue = &mut self.clues[*idx];
+                clue.unassigned -= 1;
+                if is_mine {
+                    match clue.mines_left.checked_sub(1) {
+                        Some(left) => clue.mines_left = left,
+                        None => possible = false,
+                    }
+                }
+                possible &= clue.is_possible();
+            }
+
+            if possible {
+                self.is_mine[pos] = is_mine;
+                self.mines_left -= is_mine as usize;
+                self.assign(pos + 1);
+                self.mines_left += is_mine as usize;
+                self.is_mine[pos] = false;
+            }
+
+            for (idx, clue) in self.clues_of[pos].iter().zip(saved) {
+                self.clues[*idx] = clue;
+            }
+        }
+    }
+}
+
+/// Draws the board as text, one line per row: the clue of each revealed
+/// tile, '*' for hidden mines and '.' for the other hidden tiles.
+pub fn dump(minefield: &Minefield) -> String {
+    let mut out = String::new();
+    for tiles in minefield.grid.rows() {
+        for tile in tiles.iter() {
+            match tile {
+                Tile::Revealed(clue) => write!(out, "{}", clue).unwrap(),
+                Tile::Hidden(Content::Mine, _) => out.push('*'),
+                Tile::Hidden(Content::Empty, _) => out.push('.'),
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// Proptest doesn't build for the browser.
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::backend;
+    use crate::topology;
+    use proptest::prelude::*;
+    use rand_core::SeedableRng;
+    use std::sync::Arc;
+
+    type Game = (usize, u16, u16, u32, u64, Vec<(f64, f64)>);
+
+    /// A small board of any topology, given by its index in
+    /// topology::all(), a seed, and a sequence of tiles to click, given as
+    /// fractions of the board size.
+    fn game() -> impl Strategy<Value = Game> {
+        (0..topology::all().len(), 2u16..=6, 2u16..=6).prop_flat_map(|(topology, width, height)| {
+            let cells = width as u32 * height as u32;
+            (
+                Just(topology),
+                Just(width),
+                Just(height),
+                0..=cells,
+                any::<u64>(),
+                prop::collection::vec((0.0..1.0, 0.0..1.0), 1..40),
+            )
+        })
+    }
+
+    fn play(
+        backend: Arc<dyn backend::Backend>,
+        (topology, width, height, mine_count, seed, clicks): Game,
+    ) -> Result<(), TestCaseError> {
+        let mut rng = rand_xoshiro::Xoshiro256StarStar::seed_from_u64(seed);
+        let topology = topology::all()[topology].clone();
+        let mut minefield =
+            Minefield::create_random(width, height, mine_count, topology, backend, &mut rng)
+                .unwrap();
+        minefield.set_verification(true);
+
+        for (row, col) in clicks {
+            let row = (row * height as f64) as u16;
+            let col = (col * width as f64) as u16;
+            match minefield.reveal(&mut rng, row, col) {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(err) => return Err(TestCaseError::fail(err.to_string())),
+            }
+            if let Err(err) = minefield.check_invariants() {
+                return Err(TestCaseError::fail(err.to_string()));
+            }
+            if minefield.is_all_revealed() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn diagram_agrees_with_brute_force(game in game()) {
+            play(Arc::new(backend::Diagram), game)?;
+        }
+
+        #[test]
+        fn enumerator_agrees_with_brute_force(game in game()) {
+            play(Arc::new(backend::Enumerator), game)?;
+        }
+
+        #[test]
+        fn sat_agrees_with_brute_force(game in game()) {
+            play(Arc::new(backend::Sat), game)?;
+        }
+    }
+}
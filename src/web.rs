@@ -0,0 +1,145 @@
+//! The game for the browser, driven from JavaScript.
+//!
+//! Built with `wasm-pack build --target web --out-dir web/pkg`, and played
+//! from `web/index.html`, which draws the board from what [`Game::tile`]
+//! says each tile shows, in the shape [`Game::outline`] gives.
+
+use super::backend;
+use super::minefield::{Content, Minefield, Tile, UserMarking};
+use super::neighbor_iter::NeighborIterable;
+use super::topology;
+use rand_core::SeedableRng;
+use std::sync::Arc;
+use wasm_bindgen::prelude::*;
+
+type Xoshiro = rand_xoshiro::Xoshiro256StarStar;
+
+#[wasm_bindgen]
+pub struct Game {
+    minefield: Minefield,
+    rng: Xoshiro,
+}
+
+#[wasm_bindgen]
+impl Game {
+    /// A new game, with the mines laid out at random, on a board of the
+    /// topology with the given name.
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: u16, height: u16, mine_count: u32, topology: &str) -> Result<Game, JsError> {
+        let mut seed = [0u8; 32];
+        getrandom::getrandom(&mut seed)?;
+        Self::start(
+            width,
+            height,
+            mine_count,
+            topology,
+            Xoshiro::from_seed(seed),
+        )
+    }
+
+    /// A new game, always the same for the same seed.
+    #[wasm_bindgen(js_name = withSeed)]
+    pub fn with_seed(
+        width: u16,
+        height: u16,
+        mine_count: u32,
+        topology: &str,
+        seed: u64,
+    ) -> Result<Game, JsError> {
+        Self::start(
+            width,
+            height,
+            mine_count,
+            topology,
+            Xoshiro::seed_from_u64(seed),
+        )
+    }
+
+    fn start(
+        width: u16,
+        height: u16,
+        mine_count: u32,
+        topology: &str,
+        mut rng: Xoshiro,
+    ) -> Result<Game, JsError> {
+        let topology = topology::by_name(topology)
+            .ok_or_else(|| JsError::new(&format!("unknown topology \"{}\"", topology)))?;
+        let minefield = Minefield::create_random(
+            width,
+            height,
+            mine_count,
+            topology,
+            Arc::new(backend::Diagram),
+            &mut rng,
+        )?;
+        Ok(Self { minefield, rng })
+    }
+
+    pub fn width(&self) -> u16 {
+        self.minefield.width()
+    }
+
+    pub fn height(&self) -> u16 {
+        self.minefield.height()
+    }
+
+    #[wasm_bindgen(js_name = mineCount)]
+    pub fn mine_count(&self) -> u32 {
+        self.minefield.mine_count
+    }
+
+    #[wasm_bindgen(js_name = flagCount)]
+    pub fn flag_count(&self) -> u32 {
+        self.minefield.grid.counters.flag_count
+    }
+
+    /// Reveals the tile, or the neighbors of a clue with all its mines
+    /// flagged, and returns whether the player survived.
+    pub fn reveal(&mut self, row: u16, col: u16) -> Result<bool, JsError> {
+        Ok(self.minefield.reveal(&mut self.rng, row, col)?)
+    }
+
+    #[wasm_bindgen(js_name = switchMark)]
+    pub fn switch_mark(&mut self, row: u16, col: u16) -> Result<(), JsError> {
+        Ok(self.minefield.switch_mark(row, col)?)
+    }
+
+    #[wasm_bindgen(js_name = isAllRevealed)]
+    pub fn is_all_revealed(&self) -> bool {
+        self.minefield.is_all_revealed()
+    }
+
+    /// What the tile shows, as in the notation of boards: its clue if
+    /// revealed, else ".", "F" or "?". Once the game is over, "exposed"
+    /// shows the mines left unflagged as "O", and the wrong flags as "X".
+    pub fn tile(&self, row: u16, col: u16, exposed: bool) -> String {
+        match *self.minefield.grid.get(row, col) {
+            Tile::Hidden(Content::Mine, UserMarking::None) if exposed => "O".into(),
+            Tile::Hidden(Content::Empty, UserMarking::Flag) if exposed => "X".into(),
+            Tile::Hidden(_, UserMarking::None) => ".".into(),
+            Tile::Hidden(_, UserMarking::Flag) => "F".into(),
+            Tile::Hidden(_, UserMarking::QuestionMark) => "?".into(),
+            Tile::Revealed(clue) => clue.to_string(),
+        }
+    }
+
+    /// The corners of the tile, as x and y one after the other, in a plane
+    /// where the tiles are about one unit wide.
+    pub fn outline(&self, row: u16, col: u16) -> Vec<f32> {
+        self.minefield
+            .topology()
+            .outline(row, col)
+            .into_iter()
+            .flat_map(|(x, y)| [x, y])
+            .collect()
+    }
+
+    /// Whether the tile is one of the clues proving the player lost, to be
+    /// highlighted on the board.
+    #[wasm_bindgen(js_name = isLossClue)]
+    pub fn is_loss_clue(&self, row: u16, col: u16) -> bool {
+        self.minefield
+            .loss_explanation()
+            .is_some_and(|loss| loss.clues.contains(&(row, col)))
+    }
+}
@@ -0,0 +1,38 @@
+//! Tests of the browser build, run in Node with `wasm-pack test --node`.
+
+#![cfg(target_arch = "wasm32")]
+
+use mineswapper::web::Game;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn plays_a_game() {
+    let mut game = Game::with_seed(9, 9, 10, "square", 0).unwrap();
+    assert_eq!((game.width(), game.height(), game.mine_count()), (9, 9, 10));
+
+    // Nothing is known before the first move, so it can't lose:
+    assert!(game.reveal(4, 4).unwrap());
+    assert!(game.tile(4, 4, false).parse::<u8>().is_ok());
+
+    let (row, col) = (0..9)
+        .flat_map(|row| (0..9).map(move |col| (row, col)))
+        .find(|&(row, col)| game.tile(row, col, false) == ".")
+        .unwrap();
+    game.switch_mark(row, col).unwrap();
+    assert_eq!(game.tile(row, col, false), "F");
+    assert_eq!(game.flag_count(), 1);
+}
+
+#[wasm_bindgen_test]
+fn draws_every_topology() {
+    for topology in ["square", "hexagonal", "triangular", "toroidal"] {
+        let game = Game::with_seed(5, 5, 5, topology, 0).unwrap();
+        assert!(game.outline(2, 2).len() >= 6);
+    }
+    assert!(Game::with_seed(5, 5, 5, "cubic", 0).is_err());
+}
+
+#[wasm_bindgen_test]
+fn rejects_too_many_mines() {
+    assert!(Game::with_seed(3, 3, 10, "square", 0).is_err());
+}
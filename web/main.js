@@ -0,0 +1,118 @@
+// Draws the board of the game compiled to WebAssembly, and sends it the
+// clicks of the player: left to reveal, right to switch the mark.
+
+import init, { Game } from "./pkg/mineswapper.js";
+
+const board = document.getElementById("board");
+const status = document.getElementById("status");
+const difficulty = document.getElementById("difficulty");
+const topology = document.getElementById("topology");
+
+// How many pixels a unit of the outlines of the tiles is.
+const SCALE = 30;
+const SVG = "http://www.w3.org/2000/svg";
+
+let game;
+let startTime = null;
+let ending = null;
+
+function restart() {
+  const [width, height, mines] = difficulty.value.split(",").map(Number);
+  game = new Game(width, height, mines, topology.value);
+  startTime = null;
+  ending = null;
+  draw();
+}
+
+function reveal(row, col) {
+  if (ending) {
+    return;
+  }
+  startTime ??= Date.now();
+  try {
+    const survived = game.reveal(row, col);
+    if (!survived || game.isAllRevealed()) {
+      const seconds = (Date.now() - startTime) / 1000;
+      ending = `Game time: ${seconds.toFixed(3)} seconds. `
+        + (survived ? "You won! Congratulations!" : "You lost! Try again...");
+    }
+  } catch (err) {
+    ending = `Error: ${err.message}`;
+  }
+  draw();
+}
+
+function switchMark(row, col) {
+  if (!ending) {
+    game.switchMark(row, col);
+    draw();
+  }
+}
+
+// Each tile is a polygon in the shape the topology of the board gives it,
+// with what it shows in the middle.
+function draw() {
+  board.replaceChildren();
+  let [width, height] = [0, 0];
+  for (let row = 0; row < game.height(); row++) {
+    for (let col = 0; col < game.width(); col++) {
+      const outline = game.outline(row, col).map((coord) => coord * SCALE);
+      const corners = [];
+      let [x, y] = [0, 0];
+      for (let i = 0; i < outline.length; i += 2) {
+        corners.push(`${outline[i]},${outline[i + 1]}`);
+        x += outline[i] / (outline.length / 2);
+        y += outline[i + 1] / (outline.length / 2);
+        width = Math.max(width, outline[i]);
+        height = Math.max(height, outline[i + 1]);
+      }
+
+      const g = document.createElementNS(SVG, "g");
+      const polygon = document.createElementNS(SVG, "polygon");
+      polygon.setAttribute("points", corners.join(" "));
+      const text = document.createElementNS(SVG, "text");
+      text.setAttribute("x", x);
+      text.setAttribute("y", y);
+      g.append(polygon, text);
+
+      const tile = game.tile(row, col, ending !== null);
+      if (/^[0-9]+$/.test(tile)) {
+        g.setAttribute("class", `revealed c${tile}`);
+        text.textContent = tile === "0" ? "" : tile;
+      } else {
+        text.textContent = tile === "." ? "" : tile;
+      }
+      if (game.isLossClue(row, col)) {
+        g.classList.add("clue");
+      } else if (tile === "O" || tile === "X") {
+        g.classList.add("mine");
+      }
+      g.onclick = () => reveal(row, col);
+      g.oncontextmenu = (event) => {
+        event.preventDefault();
+        switchMark(row, col);
+      };
+      board.append(g);
+    }
+  }
+  board.setAttribute("width", width);
+  board.setAttribute("height", height);
+  drawStatus();
+}
+
+function drawStatus() {
+  let text = `Flags: ${game.flagCount()}/${game.mineCount()}`;
+  if (ending) {
+    text += `. ${ending}`;
+  } else if (startTime !== null) {
+    text += `. Elapsed time: ${Math.floor((Date.now() - startTime) / 1000)} seconds`;
+  }
+  status.textContent = text;
+}
+
+await init();
+document.getElementById("restart").onclick = restart;
+difficulty.onchange = restart;
+topology.onchange = restart;
+setInterval(drawStatus, 1000);
+restart();